@@ -0,0 +1,108 @@
+use anyhow::{Context as AnyhowContext, Result};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+/// 审计日志文件名（位于会话目录）
+pub const AUDIT_LOG_FILE: &str = "tool_audit.jsonl";
+
+/// 审计日志中工具结果的最大字符数
+const AUDIT_RESULT_MAX_CHARS: usize = 2000;
+
+/// 一条工具调用审计记录
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    timestamp: String,
+    session_id: &'a str,
+    tool: &'a str,
+    arguments: &'a HashMap<String, Value>,
+    success: bool,
+    /// 成功时为工具结果，失败时为错误信息（均已截断）
+    result: String,
+}
+
+/// 工具调用审计日志：追加写入 JSONL，写盘在后台线程完成，不阻塞对话循环
+pub struct AuditLog {
+    sender: Option<Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl AuditLog {
+    /// 打开（不存在时创建）会话目录下的审计日志
+    pub fn open(storage_path: &Path) -> Result<Self> {
+        fs::create_dir_all(storage_path)
+            .with_context(|| format!("创建会话目录失败：{}", storage_path.display()))?;
+        let path = storage_path.join(AUDIT_LOG_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("打开审计日志失败：{}", path.display()))?;
+
+        let (sender, receiver) = mpsc::channel::<String>();
+        let writer = std::thread::spawn(move || {
+            let mut out = BufWriter::new(file);
+            while let Ok(line) = receiver.recv() {
+                let mut ok = writeln!(out, "{}", line).is_ok();
+                // 把已排队的记录一起写完再刷新
+                while let Ok(line) = receiver.try_recv() {
+                    ok &= writeln!(out, "{}", line).is_ok();
+                }
+                if !ok || out.flush().is_err() {
+                    tracing::warn!("⚠️ 写入审计日志失败");
+                }
+            }
+        });
+
+        Ok(AuditLog {
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// 记录一次工具调用（只入队，不等待写盘）
+    pub fn record(&self, session_id: &str, tool: &str, arguments: &HashMap<String, Value>, result: &Result<String>) {
+        let (success, text) = match result {
+            Ok(output) => (true, output.clone()),
+            Err(e) => (false, e.to_string()),
+        };
+        let entry = AuditEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            session_id,
+            tool,
+            arguments,
+            success,
+            result: truncate_chars(&text, AUDIT_RESULT_MAX_CHARS),
+        };
+        let Ok(line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(line);
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    /// 关闭通道并等待后台线程写完剩余记录
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let truncated: String = text.chars().take(max_chars).collect();
+    format!("{}…", truncated)
+}
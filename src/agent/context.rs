@@ -2,9 +2,37 @@ use anyhow::Result;
 use std::fs;
 
 use crate::config::WorkspaceConfig;
+use crate::tools::builtins::memory::{memory_path, MEMORY_WARN_BYTES};
 use crate::types::{Message, ToolCall};
 
+/// Token 估算器，可替换为更精确的实现（如真实 tokenizer）
+pub trait TokenEstimator {
+    fn estimate(&self, text: &str) -> usize;
+}
+
+/// 默认估算：约 4 个字符一个 token
+pub struct CharTokenEstimator;
+
+impl TokenEstimator for CharTokenEstimator {
+    fn estimate(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+/// 估算单条消息的 token 数（内容 + 工具调用参数）
+fn message_tokens(msg: &Message, estimator: &dyn TokenEstimator) -> usize {
+    let mut tokens = estimator.estimate(&msg.content);
+    if let Some(tool_calls) = &msg.tool_calls {
+        for tc in tool_calls {
+            tokens += estimator.estimate(&tc.function.name);
+            tokens += estimator.estimate(&tc.function.arguments.to_string());
+        }
+    }
+    tokens
+}
+
 /// 上下文 - 管理对话历史和系统提示
+#[derive(Clone)]
 pub struct Context {
     system_prompt: String,
     messages: Vec<Message>,
@@ -20,23 +48,24 @@ impl Context {
 
     /// 从 workspace 配置加载系统提示
     pub fn load_system_prompt(&mut self, config: &WorkspaceConfig) -> Result<String> {
-        let agent = fs::read_to_string(&config.agent_file)
-            .unwrap_or_default();
-        let soul = fs::read_to_string(&config.soul_file)
-            .unwrap_or_default();
-        let user = fs::read_to_string(&config.user_file)
+        let memory = fs::read_to_string(memory_path(&config.root))
             .unwrap_or_default();
 
-        let mut prompt = String::new();
-        
-        if !agent.is_empty() {
-            prompt.push_str(&format!("## 角色定义\n{}\n\n", agent.trim()));
+        if memory.len() > MEMORY_WARN_BYTES {
+            ui_eprintln!("⚠️ 长期记忆文件较大（{} 字节），建议清理：{}", memory.len(), memory_path(&config.root).display());
         }
-        if !soul.is_empty() {
-            prompt.push_str(&format!("## 对话风格\n{}\n\n", soul.trim()));
+
+        let mut prompt = String::new();
+
+        for prompt_file in &config.prompt_files {
+            let content = fs::read_to_string(config.prompt_file_path(prompt_file))
+                .unwrap_or_default();
+            if !content.trim().is_empty() {
+                prompt.push_str(&format!("## {}\n{}\n\n", prompt_file.heading, content.trim()));
+            }
         }
-        if !user.is_empty() {
-            prompt.push_str(&format!("## 用户信息\n{}\n\n", user.trim()));
+        if !memory.trim().is_empty() {
+            prompt.push_str(&format!("## 长期记忆\n{}\n\n", memory.trim()));
         }
 
         // 默认兜底
@@ -52,21 +81,44 @@ impl Context {
 
     /// 添加用户消息
     pub fn add_user(&mut self, content: &str) {
+        self.add_user_with_images(content, Vec::new());
+    }
+
+    /// 添加用户消息，并附带图片（base64，为空时与 add_user 相同）
+    pub fn add_user_with_images(&mut self, content: &str, images: Vec<String>) {
         self.messages.push(Message {
             role: "user".to_string(),
             content: content.to_string(),
             tool_calls: None,
             tool_call_id: None,
+            reasoning: None,
+            images: (!images.is_empty()).then_some(images),
+            usage: None,
+            timestamp: Some(now_timestamp()),
         });
     }
 
     /// 添加助手消息
     pub fn add_assistant(&mut self, content: &str, tool_calls: Option<Vec<ToolCall>>) {
+        self.add_assistant_with_reasoning(content, tool_calls, None);
+    }
+
+    /// 添加助手消息，并单独保存推理模型的思考过程
+    pub fn add_assistant_with_reasoning(
+        &mut self,
+        content: &str,
+        tool_calls: Option<Vec<ToolCall>>,
+        reasoning: Option<String>,
+    ) {
         self.messages.push(Message {
             role: "assistant".to_string(),
             content: content.to_string(),
             tool_calls,
             tool_call_id: None,
+            reasoning,
+            images: None,
+            usage: None,
+            timestamp: Some(now_timestamp()),
         });
     }
 
@@ -77,6 +129,10 @@ impl Context {
             content: content.to_string(),
             tool_calls: None,
             tool_call_id: Some(tool_call_id.to_string()),
+            reasoning: None,
+            images: None,
+            usage: None,
+            timestamp: Some(now_timestamp()),
         });
     }
 
@@ -90,6 +146,10 @@ impl Context {
             content: self.system_prompt.clone(),
             tool_calls: None,
             tool_call_id: None,
+            reasoning: None,
+            images: None,
+            usage: None,
+            timestamp: None,
         });
         
         // 添加对话历史
@@ -115,6 +175,63 @@ impl Context {
         }
     }
 
+    /// 估算当前上下文（系统提示 + 消息历史）的 token 数
+    pub fn estimate_tokens(&self) -> usize {
+        let estimator = CharTokenEstimator;
+        estimator.estimate(&self.system_prompt)
+            + self.messages.iter().map(|m| message_tokens(m, &estimator)).sum::<usize>()
+    }
+
+    /// 按 token 预算裁剪消息历史（使用默认估算器）
+    pub fn truncate_by_tokens(&mut self, max_tokens: usize) -> usize {
+        self.truncate_by_tokens_with(max_tokens, &CharTokenEstimator)
+    }
+
+    /// 按 token 预算裁剪消息历史，从最早的消息开始删除，返回删除的消息条数
+    ///
+    /// 带 tool_calls 的助手消息与其后的工具结果作为整体删除，且始终保留最后一组消息。
+    pub fn truncate_by_tokens_with(&mut self, max_tokens: usize, estimator: &dyn TokenEstimator) -> usize {
+        let mut total = estimator.estimate(&self.system_prompt)
+            + self.messages.iter().map(|m| message_tokens(m, estimator)).sum::<usize>();
+
+        let mut removed = 0;
+        while total > max_tokens {
+            let group_len = self.leading_group_len();
+            if group_len == 0 || group_len >= self.messages.len() {
+                break;
+            }
+            for msg in self.messages.drain(..group_len) {
+                total -= message_tokens(&msg, estimator);
+            }
+            removed += group_len;
+        }
+        removed
+    }
+
+    /// 开头一组不可拆分的消息数量：助手工具调用及其工具结果，或孤立的工具结果
+    fn leading_group_len(&self) -> usize {
+        let Some(first) = self.messages.first() else {
+            return 0;
+        };
+
+        let mut len = 1;
+        if first.role == "tool" || first.tool_calls.is_some() {
+            while self.messages.get(len).is_some_and(|m| m.role == "tool") {
+                len += 1;
+            }
+        }
+        len
+    }
+
+    /// 撤销最后一轮对话：从末尾删除到（含）最后一条用户消息，返回删除的消息数；
+    /// 没有用户消息时不做修改
+    pub fn undo_last_turn(&mut self) -> usize {
+        match self.messages.iter().rposition(|m| m.role == "user") {
+            Some(index) => self.messages.drain(index..).count(),
+            None => 0,
+        }
+    }
+
     /// 清空对话历史（保留系统提示）
     pub fn clear(&mut self) {
         self.messages.clear();
@@ -135,3 +252,8 @@ impl Context {
         self.messages.is_empty()
     }
 }
+
+/// 消息时间戳：UTC，精确到秒的 RFC3339
+fn now_timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
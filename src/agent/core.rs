@@ -1,21 +1,60 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context as AnyhowContext, Result, anyhow};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures::stream::{self, StreamExt};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 
-use crate::config::AgentConfig;
+use crate::config::{AgentConfig, Backend};
+use crate::tools::registry::is_read_only_call;
 use crate::tools::ToolExecutor;
-use crate::types::ToolCall;
+use crate::types::{image_mime_type, Message, Tool, ToolCall};
 
+use super::audit::AuditLog;
 use super::context::Context;
-use super::llm::LlmClient;
+use super::llm::{is_context_overflow, LlmClient, OutputFormat, StreamDelta};
 use super::session::SessionManager;
+use super::spinner::Spinner;
+
+/// 计划模式下写操作工具返回的结果
+const PLAN_MODE_RESULT: &str = "（计划模式：未执行）";
+
+/// 可附加图片的最大字节数
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// 倒数第二轮的工具结果后附加的提示，让模型在最后一轮直接给出答案，而不是被处理次数上限截断
+const LAST_ITERATION_NOTE: &str = "\n\n【系统提示】处理步数即将用尽，请不要再调用工具，根据已有信息直接给出最终答案。";
+
+/// 工具调用审批结果
+pub enum Approval {
+    /// 按原参数执行
+    Approve,
+    /// 拒绝执行
+    Reject,
+    /// 使用编辑后的参数执行
+    Edit(HashMap<String, Value>),
+}
+
+/// 工具调用审批回调：接收工具名和参数，返回审批结果
+pub type ApprovalCallback = Box<dyn Fn(&str, &HashMap<String, Value>) -> Approval + Send + Sync>;
 
 /// Agent - 负责对话循环
 pub struct Agent {
     llm_client: LlmClient,
     tool_executor: ToolExecutor,
     config: AgentConfig,
-    verbose: bool,
+    json_mode: bool,
+    plan_mode: bool,
+    approval: Option<ApprovalCallback>,
+    audit_log: Option<AuditLog>,
+    /// 等待模型响应时是否显示转圈提示
+    spinner: bool,
+    /// 随下一条用户消息发送的图片（base64）
+    pending_images: Vec<String>,
 }
 
 impl Agent {
@@ -23,76 +62,640 @@ impl Agent {
         llm_client: LlmClient,
         tool_executor: ToolExecutor,
         config: AgentConfig,
-        verbose: bool,
     ) -> Self {
         Agent {
             llm_client,
             tool_executor,
             config,
-            verbose,
+            json_mode: false,
+            plan_mode: false,
+            approval: None,
+            audit_log: None,
+            spinner: false,
+            pending_images: Vec::new(),
+        }
+    }
+
+    /// 当前使用的模型
+    pub fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    /// 切换模型并重建 LLM 客户端，返回原模型名
+    pub fn set_model(&mut self, model: &str) -> String {
+        let old = std::mem::replace(&mut self.config.model, model.to_string());
+        self.llm_client = LlmClient::new(self.config.clone(), self.llm_client.http_client().clone());
+        old
+    }
+
+    /// 使用会话保存的模型和采样参数（temperature、top_p、num_ctx），有变化时重建 LLM 客户端；
+    /// 会话未记录模型时保持当前模型。返回是否有变化
+    pub fn apply_session_config(&mut self, session: &AgentConfig) -> bool {
+        let model = if session.model.is_empty() {
+            self.config.model.clone()
+        } else {
+            session.model.clone()
+        };
+        if model == self.config.model
+            && session.temperature == self.config.temperature
+            && session.top_p == self.config.top_p
+            && session.num_ctx == self.config.num_ctx
+        {
+            return false;
+        }
+
+        self.config.model = model;
+        self.config.temperature = session.temperature;
+        self.config.top_p = session.top_p;
+        self.config.num_ctx = session.num_ctx;
+        self.llm_client = LlmClient::new(self.config.clone(), self.llm_client.http_client().clone());
+        true
+    }
+
+    /// 检查后端连通性并列出可用的模型（见 `LlmClient::health_check`）
+    pub async fn health_check(&self) -> Result<Vec<String>> {
+        self.llm_client.health_check().await
+    }
+
+    /// 当前使用的后端类型
+    pub fn backend(&self) -> Backend {
+        self.config.backend
+    }
+
+    /// 设置工具调用审批回调（确认模式），为 None 时直接执行
+    pub fn set_approval(&mut self, approval: Option<ApprovalCallback>) {
+        self.approval = approval;
+    }
+
+    /// 读取本地图片并附加到下一条用户消息，返回待发送的图片数
+    pub fn attach_image(&mut self, path: &Path) -> Result<usize> {
+        let size = std::fs::metadata(path)
+            .with_context(|| format!("读取图片失败：{}", path.display()))?
+            .len();
+        if size > MAX_IMAGE_BYTES {
+            return Err(anyhow!("图片过大：{}（{} 字节，上限 {} 字节）", path.display(), size, MAX_IMAGE_BYTES));
+        }
+        let data = std::fs::read(path).with_context(|| format!("读取图片失败：{}", path.display()))?;
+        if image_mime_type(&data).is_none() {
+            return Err(anyhow!("不支持的图片格式：{}（支持 PNG、JPEG、GIF、WebP）", path.display()));
+        }
+        self.pending_images.push(STANDARD.encode(&data));
+        Ok(self.pending_images.len())
+    }
+
+    /// 设置工具调用审计日志，为 None 时不记录
+    pub fn set_audit_log(&mut self, audit_log: Option<AuditLog>) {
+        self.audit_log = audit_log;
+    }
+
+    /// 已启用的工具定义（即发送给模型的工具列表）
+    pub fn tools(&self) -> &[Tool] {
+        self.tool_executor.get_tools()
+    }
+
+    /// 设置等待模型响应时是否显示转圈提示（由调用方根据终端和 config.spinner 决定）
+    pub fn set_spinner(&mut self, enabled: bool) {
+        self.spinner = enabled;
+    }
+
+    fn start_spinner(&self) -> Option<Spinner> {
+        self.spinner.then(|| Spinner::start("等待模型响应..."))
+    }
+
+    /// 是否处于 JSON 模式
+    pub fn json_mode(&self) -> bool {
+        self.json_mode
+    }
+
+    /// 设置 JSON 模式（开启后不提供工具，并校验返回内容为合法 JSON）
+    pub fn set_json_mode(&mut self, enabled: bool) {
+        self.json_mode = enabled;
+    }
+
+    /// 是否处于计划模式
+    pub fn plan_mode(&self) -> bool {
+        self.plan_mode
+    }
+
+    /// 设置计划模式（开启后只执行只读工具，写操作只打印不执行）
+    pub fn set_plan_mode(&mut self, enabled: bool) {
+        self.plan_mode = enabled;
+    }
+
+    /// 预览下一轮将发送的请求（不调用 LLM，不修改会话）
+    pub fn preview_request(&self, session_manager: &SessionManager, user_input: Option<&str>) -> Result<(String, String)> {
+        let mut ctx = session_manager.current()
+            .map(|s| s.context().clone())
+            .ok_or_else(|| anyhow!("没有当前会话"))?;
+        if let Some(input) = user_input {
+            ctx.add_user(input);
         }
+        ctx.truncate_by_tokens(self.config.max_context_tokens);
+
+        let tools = if self.json_mode { None } else { Some(self.tool_executor.get_tools()) };
+        self.llm_client.preview_request(&ctx.messages(), tools, self.json_mode)
+    }
+
+    /// 工具规划：让模型说明会调用哪些工具及理由，但不执行任何工具，也不写入会话
+    pub async fn tool_plan(&self, session_manager: &SessionManager, user_input: &str) -> Result<String> {
+        let mut ctx = session_manager.current()
+            .map(|s| s.context().clone())
+            .ok_or_else(|| anyhow!("没有当前会话"))?;
+
+        let tool_list = self.tool_executor
+            .get_tools()
+            .iter()
+            .map(|t| format!("- {}：{}（参数：{}）", t.function.name, t.function.description, t.function.parameters))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        ctx.add_user(&format!(
+            "请不要调用任何工具，也不要直接回答下面的请求。\n请按顺序列出为完成该请求你会调用哪些工具，每一步说明工具名、参数和理由；如果不需要工具，请说明原因。\n\n请求：{}\n\n可用工具：\n{}",
+            user_input, tool_list
+        ));
+        ctx.truncate_by_tokens(self.config.max_context_tokens);
+
+        let messages = ctx.messages();
+        let response = if self.config.stream {
+            self.chat_streaming(&messages, None, false).await?
+        } else {
+            self.llm_client.chat_with_retry(&messages, None, false).await?
+        };
+
+        Ok(response.content)
     }
 
-    /// 对话循环
-    pub async fn chat(&mut self, session_manager: &mut SessionManager, user_input: &str) -> Result<String> {
+    /// 压缩历史：让模型把较早的对话总结为一条摘要消息，保留最近 compact_keep_turns 轮原文，
+    /// 返回被替换的消息数（没有可压缩的内容时返回 0）
+    pub async fn compact_history(&self, session_manager: &mut SessionManager) -> Result<usize> {
         let ctx = self.current_context_mut(session_manager)
             .ok_or_else(|| anyhow!("没有当前会话"))?;
-        ctx.add_user(user_input);
 
-        for _ in 1..=self.config.max_iterations {
-            let (messages, tools) = {
-                let ctx = self.current_context(session_manager)
-                    .ok_or_else(|| anyhow!("没有当前会话"))?;
-                (ctx.messages().to_vec(), self.tool_executor.get_tools())
-            };
+        // 从最近第 N 条用户消息处切分，保证工具调用与结果不被拆开
+        let user_indices: Vec<usize> = ctx
+            .raw_messages()
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.role == "user")
+            .map(|(i, _)| i)
+            .collect();
+        let keep = self.config.compact_keep_turns;
+        let split = if keep == 0 {
+            ctx.len()
+        } else if user_indices.len() > keep {
+            user_indices[user_indices.len() - keep]
+        } else {
+            0
+        };
+        if split == 0 {
+            return Ok(0);
+        }
+
+        let mut summary_ctx = ctx.clone();
+        summary_ctx.raw_messages_mut().truncate(split);
+        summary_ctx.add_user(
+            "请将以上对话总结为简洁的要点，保留用户的目标、已确认的事实、做出的决定和尚未完成的事项，供后续对话参考。只输出总结内容。",
+        );
+
+        let response = self.llm_client
+            .chat_with_retry(&summary_ctx.messages(), None, false)
+            .await?;
+        record_usage(session_manager, &response);
+
+        let ctx = self.current_context_mut(session_manager)
+            .ok_or_else(|| anyhow!("没有当前会话"))?;
+        let mut kept = ctx.raw_messages_mut().split_off(split);
+        ctx.raw_messages_mut().clear();
+        ctx.add_assistant(&format!("【此前对话摘要】\n{}", response.content.trim()), None);
+        ctx.raw_messages_mut().append(&mut kept);
+        session_manager.auto_save();
+
+        Ok(split)
+    }
+
+    /// 手动保存当前会话（不受 session.auto_save 影响），返回写入的路径
+    pub fn save_current_session(&self, session_manager: &mut SessionManager) -> Result<PathBuf> {
+        session_manager.current_mut()
+            .ok_or_else(|| anyhow!("没有当前会话"))?
+            .touch();
+        session_manager.save_current()
+    }
+
+    /// 对话循环；`cancel` 被触发时立即中止进行中的请求和工具调用，
+    /// 并将历史恢复到本轮开始前的状态（回复完成后、生成标题时取消只放弃标题）
+    pub async fn chat(
+        &mut self,
+        session_manager: &mut SessionManager,
+        user_input: &str,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        let snapshot = self.current_context_mut(session_manager)
+            .ok_or_else(|| anyhow!("没有当前会话"))?
+            .raw_messages()
+            .to_vec();
+
+        let result = tokio::select! {
+            result = self.chat_turn(session_manager, user_input) => result,
+            _ = cancel.cancelled() => Err(anyhow!("已取消本轮对话")),
+        };
+
+        match &result {
+            // 本轮已完成并保存，生成标题时取消只放弃标题
+            Ok(_) => {
+                tokio::select! {
+                    _ = self.maybe_autotitle(session_manager) => {}
+                    _ = cancel.cancelled() => {}
+                }
+            }
+            Err(_) if cancel.is_cancelled() => {
+                if let Some(ctx) = self.current_context_mut(session_manager) {
+                    *ctx.raw_messages_mut() = snapshot;
+                }
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// 结构化输出：要求模型返回 JSON（提供 `schema` 时按该 JSON Schema 约束），
+    /// 返回内容不是合法 JSON 时重试，最终返回解析后的值；不提供工具
+    pub async fn chat_structured(
+        &mut self,
+        session_manager: &mut SessionManager,
+        prompt: &str,
+        schema: Option<&Value>,
+    ) -> Result<Value> {
+        let format = match schema {
+            Some(schema) => OutputFormat::Schema(schema),
+            None => OutputFormat::Json,
+        };
+
+        let images = std::mem::take(&mut self.pending_images);
+        let ctx = self.current_context_mut(session_manager)
+            .ok_or_else(|| anyhow!("没有当前会话"))?;
+        ctx.add_user_with_images(prompt, images);
+        // 只裁剪发送的副本，会话中保留完整历史
+        let mut request_ctx = ctx.clone();
+        let removed = request_ctx.truncate_by_tokens(self.config.max_context_tokens);
+        if removed > 0 {
+            info!("✂️ 上下文超出 token 预算，本次请求省略最早的 {} 条消息", removed);
+        }
+        let messages = request_ctx.messages();
+
+        let mut last_error = None;
+        for attempt in 1..=self.config.max_llm_retries {
+            let response = self.llm_client
+                .chat_format_with_retry(&messages, None, format)
+                .await?;
+            record_usage(session_manager, &response);
+            match serde_json::from_str::<Value>(response.content.trim()) {
+                Ok(value) => {
+                    let ctx = self.current_context_mut(session_manager).unwrap();
+                    ctx.add_assistant_with_reasoning(&response.content, None, response.reasoning.clone());
+                    session_manager.auto_save();
+                    return Ok(value);
+                }
+                Err(e) => {
+                    warn!("⚠️ 返回内容不是合法 JSON（第 {}/{} 次）：{}", attempt, self.config.max_llm_retries, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "模型未返回合法 JSON（已尝试 {} 次）：{}",
+            self.config.max_llm_retries,
+            last_error.map(|e| e.to_string()).unwrap_or_default()
+        ))
+    }
+
+    async fn chat_turn(&mut self, session_manager: &mut SessionManager, user_input: &str) -> Result<String> {
+        let images = std::mem::take(&mut self.pending_images);
+        let ctx = self.current_context_mut(session_manager)
+            .ok_or_else(|| anyhow!("没有当前会话"))?;
+        ctx.add_user_with_images(user_input, images);
+        let session_id = session_manager.current_session_id().unwrap_or_default().to_string();
+
+        let mut json_attempts = 0;
 
-            let response = self.llm_client.chat_with_retry(&messages, Some(tools)).await?;
+        for iteration in 1..=self.config.max_iterations {
+            info!("🔄 第 {}/{} 轮", iteration, self.config.max_iterations);
+            let response = self.request_llm(session_manager).await?;
 
             let ctx = self.current_context_mut(session_manager).unwrap();
 
             if let Some(tc) = &response.tool_calls {
                 if tc.len() > self.config.max_tool_calls {
-                    println!("⚠️ 过多的工具调用 ({}个)\n", tc.len());
+                    warn!("⚠️ 过多的工具调用 ({}个)", tc.len());
                     continue;
                 }
 
-                ctx.add_assistant(&response.content, Some(tc.clone()));
+                // 审批时参数可能被编辑，记录实际使用的参数以保持上下文一致
+                let (used_calls, mut results) = self.execute_tool_calls(&session_id, tc).await;
+                ctx.add_assistant_with_reasoning(&response.content, Some(used_calls), response.reasoning.clone());
+
+                if iteration + 1 == self.config.max_iterations {
+                    info!("⏳ 即将达到最大处理次数，提示模型给出最终答案");
+                    if let Some((_, r)) = results.last_mut() {
+                        r.push_str(LAST_ITERATION_NOTE);
+                    }
+                }
 
-                let results = self.execute_tool_calls(tc).await;
                 for (id, r) in results {
                     ctx.add_tool_result(&id, &r);
                 }
             } else {
-                ctx.add_assistant(&response.content, None);
-                let _ = session_manager.save_current();
+                if self.json_mode && serde_json::from_str::<Value>(&response.content).is_err() {
+                    json_attempts += 1;
+                    if json_attempts < self.config.max_llm_retries {
+                        warn!("⚠️ 返回内容不是合法 JSON，正在重试...");
+                        continue;
+                    }
+                    warn!("⚠️ 返回内容不是合法 JSON");
+                }
+
+                ctx.add_assistant_with_reasoning(&response.content, None, response.reasoning.clone());
+                session_manager.auto_save();
                 return Ok(response.content);
             }
         }
 
         let msg = "对话已达到最大处理次数，请简化问题或重新开始".to_string();
+        if self.config.stream {
+            ui_println!("🤖 AI: {}\n", msg);
+        }
         self.current_context_mut(session_manager).unwrap().add_assistant(&msg, None);
         Ok(msg)
     }
 
-    async fn execute_tool_calls(&self, tool_calls: &[ToolCall]) -> Vec<(String, String)> {
-        let mut results = Vec::new();
-        for tc in tool_calls {
-            let args = self.parse_args(tc).unwrap_or_else(|_| HashMap::new());
-            if self.verbose {
-                println!("🔧 调用：{}({})", tc.function.name, truncate_args(&args));
+    /// 首轮对话完成后为未命名的会话生成简短标题（需开启 auto_title），失败时保持未命名
+    async fn maybe_autotitle(&self, session_manager: &mut SessionManager) {
+        if !self.config.auto_title {
+            return;
+        }
+
+        let Some(session) = session_manager.current() else {
+            return;
+        };
+        let turns = session.user_turns();
+        if session.metadata().name.is_some() || turns.len() != 1 {
+            return;
+        }
+        let (input, Some(reply)) = &turns[0] else {
+            return;
+        };
+
+        let prompt = format!(
+            "请为下面的对话生成一个简短的标题（3-6 个词），只输出标题本身，不要引号和标点。\n\n用户：{}\n\n助手：{}",
+            input, reply
+        );
+        let mut title_ctx = Context::new(String::new());
+        title_ctx.add_user(&prompt);
+
+        let title = match self.llm_client.chat_with_retry(&title_ctx.messages(), None, false).await {
+            Ok(response) => {
+                record_usage(session_manager, &response);
+                clean_title(&response.content)
+            }
+            Err(e) => {
+                debug!("生成会话标题失败：{}", e);
+                return;
+            }
+        };
+
+        if let Some(title) = title {
+            debug!("🏷️ 会话标题：{}", title);
+            if let Some(session) = session_manager.current_mut() {
+                session.rename(&title);
+            }
+            session_manager.auto_save();
+        }
+    }
+
+    /// 构建当前上下文并调用 LLM；后端报告上下文超长时自动裁剪本次请求并重试
+    async fn request_llm(&self, session_manager: &mut SessionManager) -> Result<Message> {
+        // 只裁剪发送的副本，会话中保留完整历史
+        let mut request_ctx = session_manager.current()
+            .map(|s| s.context().clone())
+            .ok_or_else(|| anyhow!("没有当前会话"))?;
+        let removed = request_ctx.truncate_by_tokens(self.config.max_context_tokens);
+        if removed > 0 {
+            info!("✂️ 上下文超出 token 预算，本次请求省略最早的 {} 条消息", removed);
+        }
+        let mut auto_trims = 0;
+
+        loop {
+            let messages = request_ctx.messages();
+
+            // JSON 模式下不提供工具
+            let tools = if self.json_mode { None } else { Some(self.tool_executor.get_tools()) };
+
+            let result = if self.config.stream {
+                self.chat_streaming(&messages, tools, self.json_mode).await
             } else {
-                println!("🔧 {}", tc.function.name);
+                let spinner = self.start_spinner();
+                let result = self.llm_client
+                    .chat_with_retry(&messages, tools, self.json_mode)
+                    .await;
+                drop(spinner);
+                if let Ok(Message { reasoning: Some(reasoning), .. }) = &result {
+                    if self.config.show_reasoning {
+                        ui_println!("💭 思考：{}\n", reasoning);
+                    }
+                }
+                result
+            };
+
+            match result {
+                Err(e) if is_context_overflow(&e) && auto_trims < self.config.max_auto_trim_retries => {
+                    auto_trims += 1;
+                    // 预算按本次请求的估算量减半
+                    let budget = request_ctx.estimate_tokens() / 2;
+                    let removed = request_ctx.truncate_by_tokens(budget);
+                    if removed == 0 {
+                        return Err(e);
+                    }
+                    warn!(
+                        "✂️ 上下文超出模型窗口，本次请求再省略最早的 {} 条消息后重试（{}/{}）",
+                        removed, auto_trims, self.config.max_auto_trim_retries
+                    );
+                }
+                other => {
+                    if let Ok(message) = &other {
+                        record_usage(session_manager, message);
+                    }
+                    return other;
+                }
+            }
+        }
+    }
+
+    /// 是否流式输出（流式模式下回复已在生成时打印）
+    pub fn is_streaming(&self) -> bool {
+        self.config.stream
+    }
+
+    /// 流式调用 LLM，边生成边打印（思考过程仅在 show_reasoning 开启时打印）
+    async fn chat_streaming(&self, messages: &[Message], tools: Option<&[Tool]>, json_mode: bool) -> Result<Message> {
+        let mut started = false;
+        let mut reasoning_started = false;
+        // 转圈提示在开始输出内容时清除
+        let spinner = self.start_spinner();
+        let result = self.llm_client
+            .chat_stream_with_retry(messages, tools, json_mode, |delta| {
+                match delta {
+                    StreamDelta::Reasoning(text) => {
+                        if !self.config.show_reasoning || started {
+                            return;
+                        }
+                        if let Some(spinner) = &spinner {
+                            spinner.stop();
+                        }
+                        if !reasoning_started {
+                            ui_print!("💭 思考：");
+                            reasoning_started = true;
+                        }
+                        ui_print!("{}", text);
+                    }
+                    StreamDelta::Content(text) => {
+                        if let Some(spinner) = &spinner {
+                            spinner.stop();
+                        }
+                        if !started {
+                            if reasoning_started {
+                                ui_println!("\n");
+                            }
+                            ui_print!("🤖 AI: ");
+                            started = true;
+                        }
+                        ui_print!("{}", text);
+                    }
+                }
+                let _ = std::io::stdout().flush();
+            })
+            .await;
+        drop(spinner);
+
+        if started || reasoning_started {
+            ui_println!("\n");
+        }
+
+        result
+    }
+
+    /// 执行工具调用，返回实际使用的调用和结果，两者顺序均与输入的 tool_calls 一致
+    /// （部分后端和模型按顺序匹配工具结果）。
+    /// 计划模式和审批逐个串行处理；之后连续的只读工具并发执行（上限 max_parallel_tools），
+    /// 写操作单独执行，保证副作用按模型给出的顺序发生
+    async fn execute_tool_calls(&self, session_id: &str, tool_calls: &[ToolCall]) -> (Vec<ToolCall>, Vec<(String, String)>) {
+        let mut used_calls = Vec::new();
+        let mut outputs: Vec<Option<String>> = Vec::new();
+        // 待执行的调用：(在 used_calls 中的下标, 参数)
+        let mut pending = Vec::new();
+
+        for tc in tool_calls {
+            let mut tc = tc.clone();
+            let mut args = match self.parse_args(&tc) {
+                Ok(args) => args,
+                Err(e) => {
+                    warn!("⚠️ [{}] {} {}", tc.id, tc.function.name, e);
+                    outputs.push(Some(self.malformed_args_result(&tc.function.name, &e)));
+                    used_calls.push(tc);
+                    continue;
+                }
+            };
+
+            if self.plan_mode && !is_read_only_call(&tc.function.name, &args) {
+                ui_eprintln!(
+                    "📝 计划调用：{}\n{}",
+                    tc.function.name,
+                    serde_json::to_string_pretty(&args).unwrap_or_default()
+                );
+                outputs.push(Some(PLAN_MODE_RESULT.to_string()));
+                used_calls.push(tc);
+                continue;
+            }
+
+            if let Some(approval) = &self.approval {
+                match approval(&tc.function.name, &args) {
+                    Approval::Approve => {}
+                    Approval::Reject => {
+                        info!("🚫 已拒绝：{}", tc.function.name);
+                        outputs.push(Some("用户拒绝执行该工具调用".to_string()));
+                        used_calls.push(tc);
+                        continue;
+                    }
+                    Approval::Edit(edited) => {
+                        let value = serde_json::to_value(&edited).unwrap_or_default();
+                        // 保持与原始参数相同的表示形式（对象或 JSON 字符串）
+                        tc.function.arguments = if tc.function.arguments.is_string() {
+                            Value::String(value.to_string())
+                        } else {
+                            value
+                        };
+                        args = edited;
+                    }
+                }
             }
-            let r = self.tool_executor
-                .execute(&tc.function.name, &args)
-                .await
-                .unwrap_or_else(|e| e.to_string());
-            if self.verbose {
-                println!("✅ 完成：{}\n", truncate_result(&r));
+
+            pending.push((used_calls.len(), args));
+            outputs.push(None);
+            used_calls.push(tc);
+        }
+
+        let is_read_only = |i: usize| {
+            let (index, args) = &pending[i];
+            is_read_only_call(&used_calls[*index].function.name, args)
+        };
+        let mut start = 0;
+        while start < pending.len() {
+            let mut end = start + 1;
+            if is_read_only(start) {
+                while end < pending.len() && is_read_only(end) {
+                    end += 1;
+                }
             }
-            results.push((tc.id.clone(), r));
+
+            // buffered 按输入顺序产出结果
+            // 按下标遍历：闭包参数不含引用，返回的 future 才能满足 Send（HTTP 服务模式需要）
+            let batch: Vec<String> = stream::iter(start..end)
+                .map(|i| {
+                    let (index, args) = &pending[i];
+                    self.execute_tool_call(session_id, &used_calls[*index], args)
+                })
+                .buffered(self.config.max_parallel_tools.max(1))
+                .collect()
+                .await;
+            for ((index, _), output) in pending[start..end].iter().zip(batch) {
+                outputs[*index] = Some(output);
+            }
+            start = end;
+        }
+
+        let results = used_calls
+            .iter()
+            .zip(outputs)
+            .map(|(tc, output)| (tc.id.clone(), output.unwrap_or_default()))
+            .collect();
+        (used_calls, results)
+    }
+
+    /// 执行单个工具调用，日志以调用 ID 为前缀，便于区分并发执行的调用
+    async fn execute_tool_call(&self, session_id: &str, tc: &ToolCall, args: &HashMap<String, Value>) -> String {
+        info!("🔧 [{}] {}", tc.id, tc.function.name);
+        debug!("🔧 [{}] 调用：{}({})", tc.id, tc.function.name, truncate_args(args));
+        let result = self.tool_executor.execute(&tc.function.name, args).await;
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(session_id, &tc.function.name, args, &result);
         }
-        results
+        // 审计日志记录截断前的结果
+        let r = truncate_tool_result(result.unwrap_or_else(|e| e.to_string()), self.config.max_tool_result_chars);
+        debug!(
+            "✅ [{}] 完成：{}",
+            tc.id,
+            preview_result(&r, self.config.tool_result_preview_lines, self.config.tool_result_preview_chars)
+        );
+        r
     }
 
     fn parse_args(&self, tc: &ToolCall) -> Result<HashMap<String, Value>> {
@@ -105,28 +708,372 @@ impl Agent {
         }
     }
 
+    /// 参数解析失败时返回给模型的纠正提示：附上该工具的参数 JSON Schema，便于下一轮自行修正
+    fn malformed_args_result(&self, name: &str, error: &anyhow::Error) -> String {
+        match self.tool_executor.tool_definition(name) {
+            Some(tool) => format!(
+                "{}\n请按以下 JSON Schema 重新调用 {}（arguments 必须是合法的 JSON 对象）：\n{}",
+                error,
+                name,
+                serde_json::to_string_pretty(&tool.function.parameters).unwrap_or_default()
+            ),
+            None => format!("{}\n未知工具：{}", error, name),
+        }
+    }
+
     fn current_context_mut<'a>(&self, session_manager: &'a mut SessionManager) -> Option<&'a mut Context> {
         session_manager.current_mut().map(|s| s.context_mut())
     }
+}
+
+/// 把后端返回的 token 用量累加到当前会话
+fn record_usage(session_manager: &mut SessionManager, message: &Message) {
+    if let (Some(usage), Some(session)) = (message.usage, session_manager.current_mut()) {
+        session.add_usage(usage);
+    }
+}
+
+/// 会话标题的最大字符数
+const TITLE_MAX_CHARS: usize = 30;
 
-    fn current_context<'a>(&self, session_manager: &'a SessionManager) -> Option<&'a Context> {
-        session_manager.current().map(|s| s.context())
+/// 取模型输出的第一行，去掉引号、书名号和结尾标点
+fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.trim_start_matches("标题：").trim_start_matches("标题:");
+    let title: String = line
+        .trim_matches(|c: char| "\"'“”‘’「」《》#*".contains(c) || c.is_whitespace())
+        .trim_end_matches(['。', '.', '！', '!'])
+        .chars()
+        .take(TITLE_MAX_CHARS)
+        .collect();
+    let title = title.trim();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
     }
 }
 
 fn truncate_args(args: &HashMap<String, Value>) -> String {
     let json = serde_json::to_string(args).unwrap_or_default();
-    if json.len() > 80 {
-        format!("{}...", &json[..77])
+    // 按字符截断，避免切断中文等多字节字符
+    if json.chars().count() > 80 {
+        format!("{}...", json.chars().take(77).collect::<String>())
     } else {
         json
     }
 }
 
-fn truncate_result(result: &str) -> String {
-    if result.len() > 100 {
-        format!("{}...", &result[..97])
-    } else {
-        result.to_string()
+/// 工具结果被截断时附加的标记
+const TOOL_RESULT_TRUNCATED: &str = "…（结果已截断）";
+
+/// 按字符数截断加入上下文的工具结果（不会切断 UTF-8 字符），max_chars 为 0 时不截断
+fn truncate_tool_result(result: String, max_chars: usize) -> String {
+    match result.char_indices().nth(max_chars) {
+        Some((end, _)) if max_chars > 0 => format!("{}{}", &result[..end], TOOL_RESULT_TRUNCATED),
+        _ => result,
+    }
+}
+
+/// 截断工具结果用于终端显示（按行数和字符数，不影响发送给模型的内容）
+fn preview_result(result: &str, max_lines: usize, max_chars: usize) -> String {
+    let total_lines = result.lines().count();
+    let mut preview = String::new();
+    let mut shown_lines = 0;
+
+    for line in result.lines().take(max_lines) {
+        let remaining = max_chars.saturating_sub(preview.chars().count());
+        if remaining == 0 {
+            break;
+        }
+        if shown_lines > 0 {
+            preview.push('\n');
+        }
+        if line.chars().count() > remaining {
+            preview.extend(line.chars().take(remaining));
+            preview.push('…');
+        } else {
+            preview.push_str(line);
+        }
+        shown_lines += 1;
+    }
+
+    if shown_lines < total_lines {
+        preview.push_str(&format!("\n[… 还有 {} 行]", total_lines - shown_lines));
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ToolsConfig, WorkspaceConfig};
+    use crate::types::FunctionCall;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 本地 HTTP 服务：`GET /<毫秒>` 等待对应时间后返回 `body-<毫秒>`
+    async fn delayed_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let delay: u64 = request
+                        .split_whitespace()
+                        .nth(1)
+                        .and_then(|path| path.trim_start_matches('/').parse().ok())
+                        .unwrap_or(0);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    let body = format!("body-{}", delay);
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        url
+    }
+
+    /// 本地 Ollama 服务：按顺序返回给定的 (状态行, 响应体)（用完后重复最后一个；状态行为空时不响应），
+    /// 返回地址和每次请求发送的消息数
+    async fn llm_server(responses: Vec<(&'static str, String)>) -> (String, Arc<Mutex<Vec<usize>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut data = Vec::new();
+                let mut buf = [0u8; 8192];
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    if n == 0 {
+                        break None;
+                    }
+                    data.extend_from_slice(&buf[..n]);
+                    let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") else { continue };
+                    let length = String::from_utf8_lossy(&data[..end])
+                        .to_lowercase()
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:").map(|v| v.trim().parse().unwrap_or(0)))
+                        .unwrap_or(0);
+                    if data.len() >= end + 4 + length {
+                        break Some(data[end + 4..end + 4 + length].to_vec());
+                    }
+                };
+                let Some(body) = body else { continue };
+                let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+                let index = {
+                    let mut requests = recorded.lock().unwrap();
+                    requests.push(request["messages"].as_array().map_or(0, Vec::len));
+                    requests.len() - 1
+                };
+                let (status, body) = &responses[index.min(responses.len() - 1)];
+                if status.is_empty() {
+                    // 空状态表示不响应，连接一直挂起
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        drop(socket);
+                    });
+                    continue;
+                }
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn llm_reply(content: &str) -> (&'static str, String) {
+        let body = serde_json::json!({ "message": { "role": "assistant", "content": content }, "done": true });
+        ("200 OK", body.to_string())
+    }
+
+    /// 调用本地 LLM 服务的 Agent（非流式、不自动命名），以及存储在临时目录、
+    /// 已有 `history` 条较长消息的当前会话
+    fn llm_agent(
+        dir: &Path,
+        url: String,
+        max_context_tokens: usize,
+        history: usize,
+    ) -> (Agent, SessionManager) {
+        let client = reqwest::Client::new();
+        let tool_executor = ToolExecutor::new(dir.to_path_buf(), false, &ToolsConfig::default(), &client);
+        let config = AgentConfig {
+            base_url: url,
+            stream: false,
+            auto_title: false,
+            max_context_tokens,
+            ..AgentConfig::default()
+        };
+        let agent = Agent::new(LlmClient::new(config.clone(), client), tool_executor, config.clone());
+
+        let workspace = WorkspaceConfig { root: dir.to_path_buf(), ..WorkspaceConfig::default() };
+        let mut session_manager = SessionManager::new(dir.join("sessions"), workspace);
+        session_manager.create(None, config);
+        let ctx = session_manager.current_mut().unwrap().context_mut();
+        for i in 0..history {
+            if i % 2 == 0 {
+                ctx.add_user(&format!("问题 {} {}", i, "x".repeat(200)));
+            } else {
+                ctx.add_assistant(&format!("回答 {} {}", i, "y".repeat(200)), None);
+            }
+        }
+        (agent, session_manager)
+    }
+
+    fn stored_len(session_manager: &SessionManager) -> usize {
+        session_manager.current().unwrap().context().len()
+    }
+
+    #[tokio::test]
+    async fn token_budget_trims_request_but_keeps_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = llm_server(vec![llm_reply("好的")]).await;
+        let (mut agent, mut session_manager) = llm_agent(dir.path(), url, 300, 20);
+
+        agent.chat(&mut session_manager, "新问题", &CancellationToken::new()).await.unwrap();
+
+        // 发送的请求被裁剪，会话和保存的文件仍有全部 20 条历史加本轮两条
+        let sent = requests.lock().unwrap()[0];
+        assert!(sent < 20, "{}", sent);
+        assert_eq!(stored_len(&session_manager), 22);
+        let id = session_manager.current_session_id().unwrap().to_string();
+        let saved = crate::agent::session::Session::load(&dir.path().join("sessions").join(format!("{}.json", id))).unwrap();
+        assert_eq!(saved.context().len(), 22);
+    }
+
+    #[tokio::test]
+    async fn context_overflow_retry_trims_request_but_keeps_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let overflow = ("400 Bad Request", r#"{"error":"context length exceeded"}"#.to_string());
+        let (url, requests) = llm_server(vec![overflow, llm_reply("好的")]).await;
+        let (mut agent, mut session_manager) = llm_agent(dir.path(), url, 1_000_000, 20);
+
+        agent.chat(&mut session_manager, "新问题", &CancellationToken::new()).await.unwrap();
+
+        let sent = requests.lock().unwrap().clone();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1] < sent[0], "{:?}", sent);
+        assert_eq!(stored_len(&session_manager), 22);
+    }
+
+    #[tokio::test]
+    async fn cancel_during_autotitle_keeps_finished_turn() {
+        let dir = tempfile::tempdir().unwrap();
+        // 第二个请求（生成标题）不响应
+        let (url, requests) = llm_server(vec![llm_reply("你好！"), ("", String::new())]).await;
+        let (mut agent, mut session_manager) = llm_agent(dir.path(), url, 1_000_000, 0);
+        agent.config.auto_title = true;
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        let watched = requests.clone();
+        tokio::spawn(async move {
+            while watched.lock().unwrap().len() < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            trigger.cancel();
+        });
+
+        let reply = agent.chat(&mut session_manager, "你好", &cancel).await.unwrap();
+        assert_eq!(reply, "你好！");
+        assert_eq!(stored_len(&session_manager), 2);
+        assert!(session_manager.current().unwrap().metadata().name.is_none());
+    }
+
+    fn agent(workspace: &Path, max_parallel_tools: usize) -> Agent {
+        let client = reqwest::Client::new();
+        let tools_config = ToolsConfig { web_retries: 1, ..ToolsConfig::default() };
+        let tool_executor = ToolExecutor::new(workspace.to_path_buf(), false, &tools_config, &client);
+        let config = AgentConfig { max_parallel_tools, ..AgentConfig::default() };
+        Agent::new(LlmClient::new(config.clone(), client), tool_executor, config)
+    }
+
+    fn call(id: &str, name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            r#type: Some("function".to_string()),
+            function: FunctionCall { name: name.to_string(), index: None, arguments },
+        }
+    }
+
+    fn fetch(id: &str, url: &str, delay_ms: u64) -> ToolCall {
+        call(id, "web_fetch", serde_json::json!({ "url": format!("{}/{}", url, delay_ms) }))
+    }
+
+    #[test]
+    fn truncate_args_keeps_multibyte_characters_whole() {
+        let mut args = HashMap::new();
+        args.insert("content".to_string(), Value::String("中文参数".repeat(30)));
+        let truncated = truncate_args(&args);
+        assert_eq!(truncated.chars().count(), 80);
+        assert!(truncated.ends_with("..."), "{}", truncated);
+
+        let mut short = HashMap::new();
+        short.insert("path".to_string(), Value::String("笔记.md".to_string()));
+        assert_eq!(truncate_args(&short), r#"{"path":"笔记.md"}"#);
+    }
+
+    #[tokio::test]
+    async fn concurrent_results_keep_input_order() {
+        let workspace = tempfile::tempdir().unwrap();
+        let url = delayed_server().await;
+        let agent = agent(workspace.path(), 4);
+        // 先发出的调用后完成
+        let calls = [fetch("c1", &url, 600), fetch("c2", &url, 400), fetch("c3", &url, 200), fetch("c4", &url, 0)];
+
+        let started = Instant::now();
+        let (used, results) = agent.execute_tool_calls("test", &calls).await;
+        let elapsed = started.elapsed();
+
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["c1", "c2", "c3", "c4"]);
+        let used_ids: Vec<&str> = used.iter().map(|tc| tc.id.as_str()).collect();
+        assert_eq!(used_ids, ids);
+        for ((_, output), delay) in results.iter().zip([600, 400, 200, 0]) {
+            assert!(output.contains(&format!("body-{}", delay)), "{}", output);
+        }
+        // 并发执行：总耗时接近最慢的调用（600 ms），而不是全部之和（1200 ms）
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn write_between_reads_keeps_order() {
+        let workspace = tempfile::tempdir().unwrap();
+        let url = delayed_server().await;
+        let agent = agent(workspace.path(), 2);
+        let calls = [
+            fetch("r1", &url, 200),
+            fetch("r2", &url, 0),
+            call("w1", "fs_write", serde_json::json!({ "path": "out.txt", "content": "x" })),
+            fetch("r3", &url, 150),
+            fetch("r4", &url, 0),
+        ];
+
+        let (_, results) = agent.execute_tool_calls("test", &calls).await;
+
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["r1", "r2", "w1", "r3", "r4"]);
+        assert!(results[0].1.contains("body-200"));
+        assert!(results[2].1.contains("文件已写入"), "{}", results[2].1);
+        assert!(results[3].1.contains("body-150"));
+        assert!(workspace.path().join("out.txt").exists());
     }
 }
@@ -1,83 +1,1236 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, Response};
+use serde_json::Value;
+use tracing::warn;
 
-use crate::types::{Message, OllamaRequest, OllamaResponse, Tool};
-use crate::config::AgentConfig;
+use crate::types::{
+    Message, OllamaGenerateRequest, OllamaGenerateResponse, OllamaOptions, OllamaRequest, OllamaResponse,
+    OllamaStreamChunk, OpenAiFunctionCall,
+    OpenAiMessage, OpenAiRequest, OpenAiResponse, OpenAiStreamChunk, OpenAiToolCall,
+    ResponseFormat, TokenUsage, Tool, ToolCall,
+};
+use crate::config::{AgentConfig, Backend};
+use crate::http;
+
+use super::reasoning::{merge_reasoning, split_reasoning, ReasoningSplitter};
+
+/// 回复格式约束
+#[derive(Debug, Clone, Copy)]
+pub enum OutputFormat<'a> {
+    /// 不限制
+    Text,
+    /// 任意合法 JSON（JSON 模式）
+    Json,
+    /// 符合指定 JSON Schema 的 JSON
+    Schema(&'a Value),
+}
+
+impl OutputFormat<'_> {
+    fn from_json_mode(json_mode: bool) -> Self {
+        if json_mode {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Text
+        }
+    }
+}
+
+/// 流式输出的增量：推理模型的思考过程与正文分开下发
+pub enum StreamDelta<'a> {
+    Content(&'a str),
+    Reasoning(&'a str),
+}
+
+/// 后端因上下文超出模型窗口而拒绝请求
+#[derive(Debug)]
+pub struct ContextOverflowError(pub String);
+
+impl std::fmt::Display for ContextOverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "上下文超出模型窗口：{}", self.0)
+    }
+}
+
+impl std::error::Error for ContextOverflowError {}
+
+/// Ollama 中请求的模型尚未拉取
+#[derive(Debug)]
+pub struct ModelNotFoundError(pub String);
+
+impl std::fmt::Display for ModelNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "模型未找到：{}，请先运行 `ollama pull {}` 拉取模型", self.0, self.0)
+    }
+}
+
+impl std::error::Error for ModelNotFoundError {}
+
+/// 后端返回的非成功 HTTP 状态（上下文超长、模型未找到之外的情况）
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: reqwest::StatusCode,
+    pub message: String,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+impl HttpStatusError {
+    /// 4xx 表示请求本身有问题，重试也不会成功；408（超时）和 429（限流）除外
+    fn is_permanent(&self) -> bool {
+        self.status.is_client_error()
+            && self.status != reqwest::StatusCode::REQUEST_TIMEOUT
+            && self.status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    }
+}
+
+/// 判断错误是否为上下文超长
+pub fn is_context_overflow(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<ContextOverflowError>())
+}
+
+/// 判断错误是否为模型未找到
+pub fn is_model_not_found(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<ModelNotFoundError>())
+}
+
+/// 判断错误是否值得重试：上下文超长、模型未找到和 4xx（408、429 除外）重试也不会成功；
+/// 5xx、超时和连接失败会重试
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    let permanent_status = error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<HttpStatusError>())
+        .any(HttpStatusError::is_permanent);
+    !permanent_status && !is_context_overflow(error) && !is_model_not_found(error)
+}
+
+/// 第 attempt 次失败后的重试等待时间：retry_base_ms × retry_multiplier^attempt，不超过 max_backoff_ms
+pub fn retry_delay(config: &AgentConfig, attempt: usize) -> std::time::Duration {
+    let exponent = i32::try_from(attempt).unwrap_or(i32::MAX);
+    let delay = config.retry_base_ms as f64 * config.retry_multiplier.powi(exponent);
+    let max = config.max_backoff_ms as f64;
+    std::time::Duration::from_millis(delay.min(max) as u64)
+}
+
+/// 识别 Ollama 的模型未找到错误（如 `model "x" not found, try pulling it first`）
+fn looks_like_model_not_found(body: &str) -> bool {
+    let body = body.to_lowercase();
+    body.contains("model") && body.contains("not found")
+}
+
+/// 根据状态码和错误信息识别上下文超长（各后端措辞不同，按常见关键字匹配）
+fn looks_like_context_overflow(status: reqwest::StatusCode, body: &str) -> bool {
+    const PATTERNS: &[&str] = &[
+        "context length",
+        "context window",
+        "context_length_exceeded",
+        "maximum context",
+        "too many tokens",
+        "prompt is too long",
+        "exceeds the available context",
+    ];
+    let body = body.to_lowercase();
+    status == reqwest::StatusCode::PAYLOAD_TOO_LARGE || PATTERNS.iter().any(|p| body.contains(p))
+}
 
 pub struct LlmClient {
     client: Client,
     config: AgentConfig,
+    /// 每个 LLM 请求附加的请求头（认证 + 自定义）
+    headers: HeaderMap,
 }
 
 impl LlmClient {
-    pub fn new(config: AgentConfig) -> Self {
-        LlmClient {
-            client: Client::new(),
-            config,
+    pub fn new(config: AgentConfig, client: Client) -> Self {
+        // 请求头已在 Config::validate 中校验
+        let mut headers = http::header_map(&config.headers).unwrap_or_default();
+        if let Some(key) = config.api_key.as_deref().filter(|k| !k.is_empty()) {
+            if let Ok(mut value) = HeaderValue::from_str(&format!("Bearer {}", key)) {
+                value.set_sensitive(true);
+                headers.insert(AUTHORIZATION, value);
+            }
+        }
+        LlmClient { client, config, headers }
+    }
+
+    /// 底层 HTTP 客户端
+    pub fn http_client(&self) -> &Client {
+        &self.client
+    }
+
+    pub async fn chat_with_retry(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        json_mode: bool,
+    ) -> Result<Message> {
+        self.chat_format_with_retry(messages, tools, OutputFormat::from_json_mode(json_mode))
+            .await
+    }
+
+    /// 带重试的非流式调用，可指定回复格式（如 JSON Schema）
+    pub async fn chat_format_with_retry(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        format: OutputFormat<'_>,
+    ) -> Result<Message> {
+        let error = match self.retry_chat(messages, tools, format).await {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
+        match self.fallback_client(&error) {
+            Some(fallback) => fallback
+                .chat(messages, tools, format)
+                .await
+                .with_context(|| fallback_failed(&self.config.model, &fallback.config.model, &error)),
+            None => Err(error),
         }
     }
 
-    pub async fn chat_with_retry(&self, messages: &[Message], tools: Option<&[Tool]>) -> Result<Message> {
+    /// 用主模型调用并按 max_llm_retries 重试
+    async fn retry_chat(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        format: OutputFormat<'_>,
+    ) -> Result<Message> {
         let mut last_error = None;
 
         for attempt in 1..=self.config.max_llm_retries {
-            match self.chat(messages, tools).await {
+            match self.chat(messages, tools, format).await {
                 Ok(response) => return Ok(response),
+                // 上下文超长交给调用方裁剪，模型未找到等错误重试无意义
+                Err(e) if !is_retryable(&e) => return Err(e),
                 Err(e) => {
                     last_error = Some(e);
                     if attempt < self.config.max_llm_retries {
-                        println!(
+                        warn!(
                             "⚠️ LLM 调用失败 (尝试 {}/{})，正在重试...",
                             attempt, self.config.max_llm_retries
                         );
-                        tokio::time::sleep(tokio::time::Duration::from_millis(
-                            100 * (1 << attempt),
-                        ))
-                        .await;
+                        tokio::time::sleep(retry_delay(&self.config, attempt)).await;
                     }
                 }
             }
         }
 
-        Err(anyhow::anyhow!(
-            "LLM 调用在 {} 次尝试后仍然失败：{:?}",
-            self.config.max_llm_retries,
-            last_error
-        ))
+        let error = last_error.unwrap_or_else(|| anyhow::anyhow!("agent.max_llm_retries 为 0，未发起调用"));
+        Err(error.context(format!("LLM 调用在 {} 次尝试后仍然失败", self.config.max_llm_retries)))
+    }
+
+    /// 流式调用（带重试）：已输出内容后不再重试，避免重复输出
+    pub async fn chat_stream_with_retry<F: FnMut(StreamDelta)>(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        json_mode: bool,
+        mut on_delta: F,
+    ) -> Result<Message> {
+        let mut emitted = false;
+        let result = self
+            .retry_chat_stream(messages, tools, json_mode, |delta| {
+                emitted = true;
+                on_delta(delta);
+            })
+            .await;
+        let error = match result {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
+        // 已输出部分内容时不切换模型（fallback_client 会记录切换日志，需先判断）
+        if emitted {
+            return Err(error);
+        }
+        match self.fallback_client(&error) {
+            Some(fallback) => fallback
+                .chat_stream(messages, tools, json_mode, on_delta)
+                .await
+                .with_context(|| fallback_failed(&self.config.model, &fallback.config.model, &error)),
+            None => Err(error),
+        }
+    }
+
+    /// 用主模型流式调用并按 max_llm_retries 重试
+    async fn retry_chat_stream<F: FnMut(StreamDelta)>(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        json_mode: bool,
+        mut on_delta: F,
+    ) -> Result<Message> {
+        let mut last_error = None;
+
+        for attempt in 1..=self.config.max_llm_retries {
+            let mut emitted = false;
+            let result = self
+                .chat_stream(messages, tools, json_mode, |delta| {
+                    emitted = true;
+                    on_delta(delta);
+                })
+                .await;
+
+            match result {
+                Ok(response) => return Ok(response),
+                Err(e) if emitted || !is_retryable(&e) => return Err(e),
+                Err(e) => {
+                    last_error = Some(e);
+                    if attempt < self.config.max_llm_retries {
+                        warn!(
+                            "⚠️ LLM 调用失败 (尝试 {}/{})，正在重试...",
+                            attempt, self.config.max_llm_retries
+                        );
+                        tokio::time::sleep(retry_delay(&self.config, attempt)).await;
+                    }
+                }
+            }
+        }
+
+        let error = last_error.unwrap_or_else(|| anyhow::anyhow!("agent.max_llm_retries 为 0，未发起调用"));
+        Err(error.context(format!("LLM 调用在 {} 次尝试后仍然失败", self.config.max_llm_retries)))
+    }
+
+    /// 主模型调用失败后使用的备用模型客户端；上下文超长交给调用方裁剪，不切换模型
+    fn fallback_client(&self, error: &anyhow::Error) -> Option<LlmClient> {
+        let model = self.config.fallback_model.as_deref()?.trim();
+        if model.is_empty() || model == self.config.model || is_context_overflow(error) {
+            return None;
+        }
+
+        warn!("⚠️ 模型 {} 调用失败，改用备用模型 {} 重试：{}", self.config.model, model, error);
+        Some(LlmClient {
+            client: self.client.clone(),
+            config: AgentConfig {
+                model: model.to_string(),
+                fallback_model: None,
+                ..self.config.clone()
+            },
+            headers: self.headers.clone(),
+        })
+    }
+
+    /// 流式调用：逐块读取响应，通过回调输出内容增量，返回完整消息
+    pub async fn chat_stream<F: FnMut(StreamDelta)>(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        json_mode: bool,
+        on_delta: F,
+    ) -> Result<Message> {
+        match self.config.backend {
+            Backend::Ollama => {
+                self.chat_stream_ollama(messages, tools, OutputFormat::from_json_mode(json_mode), on_delta)
+                    .await
+            }
+            Backend::OpenAiCompatible => {
+                self.chat_stream_openai(messages, tools, OutputFormat::from_json_mode(json_mode), on_delta)
+                    .await
+            }
+        }
+    }
+
+    /// 列出后端已安装/可用的模型（Ollama: /api/tags，OpenAI 兼容: /models）
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let (url, list_key, name_key) = match self.config.backend {
+            Backend::Ollama => (format!("{}/api/tags", self.config.base_url), "models", "name"),
+            Backend::OpenAiCompatible => (
+                format!("{}/models", self.config.base_url.trim_end_matches('/')),
+                "data",
+                "id",
+            ),
+        };
+
+        let response = self
+            .client
+            .get(&url)
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .with_context(|| format!("获取模型列表失败：{}", url))?;
+
+        let status = response.status();
+        let text = read_body(response, self.config.max_response_bytes).await?;
+
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("获取模型列表失败：{} - {}", status, text));
+        }
+
+        let value: serde_json::Value = serde_json::from_str(&text)
+            .with_context(|| format!("解析模型列表失败，原始内容：{}", text))?;
+
+        let mut models: Vec<String> = value
+            .get(list_key)
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.get(name_key).and_then(|v| v.as_str()))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        models.sort();
+        Ok(models)
     }
 
-    async fn chat(&self, messages: &[Message], tools: Option<&[Tool]>) -> Result<Message> {
-        let request = OllamaRequest {
+    /// 启动时的连通性检查：返回可用模型列表；服务无法连接时返回带提示的错误，
+    /// 其他原因（如后端不提供模型列表接口）拿不到列表时返回空列表
+    pub async fn health_check(&self) -> Result<Vec<String>> {
+        match self.list_models().await {
+            Ok(models) => Ok(models),
+            Err(e) if is_unreachable(&e) => {
+                let hint = match self.config.backend {
+                    Backend::Ollama => "请确认 Ollama 已启动（ollama serve）",
+                    Backend::OpenAiCompatible => "请确认 LLM 服务已启动",
+                };
+                Err(e.context(format!(
+                    "无法连接到 {}，{}，或检查 agent.base_url",
+                    self.config.base_url, hint
+                )))
+            }
+            Err(e) => {
+                tracing::debug!("⚠️ {}", e);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// 单次补全（Ollama `/api/generate`）：只发送提示本身，不带系统提示、历史和工具，也不重试
+    pub async fn generate(&self, prompt: &str) -> Result<String> {
+        if self.config.backend != Backend::Ollama {
+            return Err(anyhow::anyhow!("gen 仅支持 Ollama 后端（/api/generate），其他后端请使用 ask"));
+        }
+
+        let request = OllamaGenerateRequest {
             model: self.config.model.clone(),
-            messages: messages.to_vec(),
-            tools: tools.map(|t| t.to_vec()),
+            prompt: prompt.to_string(),
             stream: false,
+            options: self.ollama_options(),
+            keep_alive: self.config.keep_alive.as_deref().map(keep_alive_value),
         };
+        let url = format!("{}/api/generate", self.config.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.headers.clone())
+            .json(&request)
+            .send()
+            .await
+            .context("调用 Ollama API 失败")?;
+
+        let status = response.status();
+        let text = read_body(response, self.config.max_response_bytes).await?;
+
+        if !status.is_success() {
+            return Err(ollama_api_error(&self.config.model, status, text));
+        }
+
+        let generate_response: OllamaGenerateResponse = serde_json::from_str(&text)
+            .with_context(|| format!("解析 Ollama 响应失败，原始内容：{}", text))?;
+
+        if let Some(err) = generate_response.error {
+            return Err(ollama_error(&self.config.model, err));
+        }
+
+        Ok(split_reasoning(&generate_response.response).0)
+    }
+
+    /// 构建请求但不发送，返回目标 URL 和格式化后的请求体（用于调试）
+    pub fn preview_request(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        json_mode: bool,
+    ) -> Result<(String, String)> {
+        let stream = self.config.stream;
+        let format = OutputFormat::from_json_mode(json_mode);
+        match self.config.backend {
+            Backend::Ollama => Ok((
+                format!("{}/api/chat", self.config.base_url),
+                serde_json::to_string_pretty(&self.ollama_request(messages, tools, format, stream))?,
+            )),
+            Backend::OpenAiCompatible => Ok((
+                format!("{}/chat/completions", self.config.base_url.trim_end_matches('/')),
+                serde_json::to_string_pretty(&self.openai_request(messages, tools, format, stream))?,
+            )),
+        }
+    }
+
+    async fn chat(&self, messages: &[Message], tools: Option<&[Tool]>, format: OutputFormat<'_>) -> Result<Message> {
+        let message = match self.config.backend {
+            Backend::Ollama => self.chat_ollama(messages, tools, format).await,
+            Backend::OpenAiCompatible => self.chat_openai(messages, tools, format).await,
+        }?;
+        Ok(separate_reasoning(message))
+    }
+
+    fn ollama_request(&self, messages: &[Message], tools: Option<&[Tool]>, format: OutputFormat, stream: bool) -> OllamaRequest {
+        OllamaRequest {
+            model: self.config.model.clone(),
+            // 思考过程只保存在会话中，不再发送给模型
+            messages: messages
+                .iter()
+                .map(|m| Message {
+                    role: self.map_role(&m.role),
+                    reasoning: None,
+                    timestamp: None,
+                    ..m.clone()
+                })
+                .collect(),
+            tools: tools.map(|t| t.to_vec()),
+            stream,
+            format: match format {
+                OutputFormat::Text => None,
+                OutputFormat::Json => Some(Value::String("json".to_string())),
+                OutputFormat::Schema(schema) => Some(schema.clone()),
+            },
+            options: self.ollama_options(),
+            keep_alive: self.config.keep_alive.as_deref().map(keep_alive_value),
+        }
+    }
+
+    fn ollama_options(&self) -> Option<OllamaOptions> {
+        let config = &self.config;
+        if config.temperature.is_none() && config.top_p.is_none() && config.num_ctx.is_none() {
+            return None;
+        }
+        Some(OllamaOptions {
+            temperature: config.temperature,
+            top_p: config.top_p,
+            num_ctx: config.num_ctx,
+        })
+    }
+
+    fn openai_request(&self, messages: &[Message], tools: Option<&[Tool]>, format: OutputFormat, stream: bool) -> OpenAiRequest {
+        OpenAiRequest {
+            model: self.config.model.clone(),
+            messages: messages
+                .iter()
+                .map(|m| OpenAiMessage {
+                    role: self.map_role(&m.role),
+                    ..OpenAiMessage::from(m)
+                })
+                .collect(),
+            tools: tools.map(|t| t.to_vec()),
+            stream,
+            response_format: match format {
+                OutputFormat::Text => None,
+                OutputFormat::Json => Some(ResponseFormat {
+                    r#type: "json_object".to_string(),
+                    json_schema: None,
+                }),
+                OutputFormat::Schema(schema) => Some(ResponseFormat {
+                    r#type: "json_schema".to_string(),
+                    json_schema: Some(serde_json::json!({ "name": "response", "schema": schema })),
+                }),
+            },
+            temperature: self.config.temperature,
+            top_p: self.config.top_p,
+        }
+    }
+
+    /// 按 role_map 替换发送给后端的角色名（未配置的角色原样发送）
+    fn map_role(&self, role: &str) -> String {
+        self.config.role_map.get(role).cloned().unwrap_or_else(|| role.to_string())
+    }
+
+    async fn chat_ollama(&self, messages: &[Message], tools: Option<&[Tool]>, format: OutputFormat<'_>) -> Result<Message> {
+        let request = self.ollama_request(messages, tools, format, false);
 
         let url = format!("{}/api/chat", self.config.base_url);
 
         let response = self
             .client
             .post(&url)
+            .headers(self.headers.clone())
             .json(&request)
             .send()
             .await
             .context("调用 Ollama API 失败")?;
 
         let status = response.status();
-        let text = response.text().await.context("读取响应失败")?;
+        let text = read_body(response, self.config.max_response_bytes).await?;
 
         if !status.is_success() {
-            return Err(anyhow::anyhow!("Ollama API 错误：{} - {}", status, text));
+            return Err(ollama_api_error(&self.config.model, status, text));
         }
 
         let ollama_response: OllamaResponse = serde_json::from_str(&text)
             .with_context(|| format!("解析 Ollama 响应失败，原始内容：{}", text))?;
 
         if let Some(err) = ollama_response.error {
-            return Err(anyhow::anyhow!("Ollama 错误：{}", err));
+            return Err(ollama_error(&self.config.model, err));
         }
 
-        Ok(ollama_response.message)
+        let usage = ollama_response.usage();
+        Ok(Message { usage, ..ollama_response.message })
+    }
+
+    async fn chat_openai(&self, messages: &[Message], tools: Option<&[Tool]>, format: OutputFormat<'_>) -> Result<Message> {
+        let request = self.openai_request(messages, tools, format, false);
+
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.headers.clone())
+            .json(&request)
+            .send()
+            .await
+            .context("调用 OpenAI 兼容 API 失败")?;
+
+        let status = response.status();
+        let text = read_body(response, self.config.max_response_bytes).await?;
+
+        if !status.is_success() {
+            return Err(api_error("OpenAI 兼容 API 错误", status, text));
+        }
+
+        let openai_response: OpenAiResponse = serde_json::from_str(&text)
+            .with_context(|| format!("解析 OpenAI 兼容响应失败，原始内容：{}", text))?;
+
+        let choice = openai_response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("OpenAI 兼容响应中没有 choices：{}", text))?;
+
+        Ok(Message {
+            usage: openai_response.usage.map(TokenUsage::from),
+            ..Message::from(choice.message)
+        })
+    }
+
+    async fn chat_stream_ollama<F: FnMut(StreamDelta)>(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        format: OutputFormat<'_>,
+        mut on_delta: F,
+    ) -> Result<Message> {
+        let request = self.ollama_request(messages, tools, format, true);
+
+        let url = format!("{}/api/chat", self.config.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.headers.clone())
+            .json(&request)
+            .send()
+            .await
+            .context("调用 Ollama API 失败")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = read_body(response, self.config.max_response_bytes).await?;
+            return Err(ollama_api_error(&self.config.model, status, text));
+        }
+
+        let mut collector = StreamCollector::default();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        let mut role = "assistant".to_string();
+        let mut usage = None;
+
+        read_lines(response, self.config.max_response_bytes, |line| {
+            let chunk: OllamaStreamChunk = serde_json::from_str(line)
+                .with_context(|| format!("解析 Ollama 流式响应失败，原始内容：{}", line))?;
+
+            if let Some(err) = chunk.error {
+                return Err(ollama_error(&self.config.model, err));
+            }
+            if let Some(chunk_usage) = chunk.usage() {
+                usage = Some(chunk_usage);
+            }
+
+            if let Some(message) = chunk.message {
+                if !message.role.is_empty() {
+                    role = message.role;
+                }
+                if let Some(thinking) = message.reasoning.filter(|t| !t.is_empty()) {
+                    collector.push_reasoning(&thinking, &mut on_delta);
+                }
+                if !message.content.is_empty() {
+                    collector.push_content(&message.content, &mut on_delta);
+                }
+                // 工具调用可能出现在任意块中（通常在流的末尾），逐块累积
+                if let Some(tc) = message.tool_calls {
+                    tool_calls.extend(tc);
+                }
+            }
+
+            Ok(chunk.done)
+        })
+        .await?;
+
+        let (content, reasoning) = collector.finish(&mut on_delta);
+        Ok(Message {
+            role,
+            content,
+            tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+            tool_call_id: None,
+            reasoning,
+            images: None,
+            usage,
+            timestamp: None,
+        })
+    }
+
+    async fn chat_stream_openai<F: FnMut(StreamDelta)>(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        format: OutputFormat<'_>,
+        mut on_delta: F,
+    ) -> Result<Message> {
+        let request = self.openai_request(messages, tools, format, true);
+
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.headers.clone())
+            .json(&request)
+            .send()
+            .await
+            .context("调用 OpenAI 兼容 API 失败")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = read_body(response, self.config.max_response_bytes).await?;
+            return Err(api_error("OpenAI 兼容 API 错误", status, text));
+        }
+
+        let mut collector = StreamCollector::default();
+        let mut tool_calls: Vec<OpenAiToolCall> = Vec::new();
+        let mut role = "assistant".to_string();
+
+        read_lines(response, self.config.max_response_bytes, |line| {
+            // SSE 格式：每行 "data: {...}"，以 "data: [DONE]" 结束
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => return Ok(false),
+            };
+            if data == "[DONE]" {
+                return Ok(true);
+            }
+
+            let chunk: OpenAiStreamChunk = serde_json::from_str(data)
+                .with_context(|| format!("解析 OpenAI 兼容流式响应失败，原始内容：{}", data))?;
+
+            for choice in chunk.choices {
+                let delta = choice.delta;
+                if let Some(r) = delta.role {
+                    role = r;
+                }
+                if let Some(text) = delta.content.filter(|t| !t.is_empty()) {
+                    collector.push_content(&text, &mut on_delta);
+                }
+                // 工具调用按 index 分片下发，需要拼接 name 和 arguments；
+                // index 只能沿用已有的或紧接其后，避免按服务端给出的序号无限分配
+                for tc in delta.tool_calls.unwrap_or_default() {
+                    if tc.index > tool_calls.len() || tc.index >= self.config.max_tool_calls {
+                        return Err(anyhow::anyhow!(
+                            "流式响应中的工具调用序号无效：{}（已收到 {} 个，上限 max_tool_calls {}）",
+                            tc.index,
+                            tool_calls.len(),
+                            self.config.max_tool_calls
+                        ));
+                    }
+                    if tc.index == tool_calls.len() {
+                        tool_calls.push(OpenAiToolCall {
+                            id: String::new(),
+                            r#type: "function".to_string(),
+                            function: OpenAiFunctionCall {
+                                name: String::new(),
+                                arguments: String::new(),
+                            },
+                        });
+                    }
+                    let entry = &mut tool_calls[tc.index];
+                    if let Some(id) = tc.id {
+                        entry.id = id;
+                    }
+                    if let Some(f) = tc.function {
+                        if let Some(name) = f.name {
+                            entry.function.name.push_str(&name);
+                        }
+                        if let Some(args) = f.arguments {
+                            entry.function.arguments.push_str(&args);
+                        }
+                    }
+                }
+            }
+
+            Ok(false)
+        })
+        .await?;
+
+        let (content, reasoning) = collector.finish(&mut on_delta);
+        Ok(Message {
+            role,
+            content,
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls.into_iter().map(ToolCall::from).collect())
+            },
+            tool_call_id: None,
+            reasoning,
+            images: None,
+            usage: None,
+            timestamp: None,
+        })
+    }
+}
+
+/// 是否为连接失败或超时（服务未启动、地址错误等）
+fn is_unreachable(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout())
+}
+
+/// Ollama 只接受带单位的时长字符串或数字秒数，纯数字（如 "-1"）按数字发送
+fn keep_alive_value(keep_alive: &str) -> Value {
+    let keep_alive = keep_alive.trim();
+    match keep_alive.parse::<i64>() {
+        Ok(seconds) => Value::from(seconds),
+        Err(_) => Value::String(keep_alive.to_string()),
+    }
+}
+
+/// 把非流式回复正文中的推理块移到 reasoning 字段
+fn separate_reasoning(mut message: Message) -> Message {
+    let (content, inline) = split_reasoning(&message.content);
+    message.content = content;
+    message.reasoning = merge_reasoning(message.reasoning.take(), inline);
+    message
+}
+
+/// 流式响应的累积结果：正文和思考过程分开累积，并通过回调分别输出
+#[derive(Default)]
+struct StreamCollector {
+    splitter: ReasoningSplitter,
+    content: String,
+    reasoning: String,
+}
+
+impl StreamCollector {
+    /// 正文增量（可能包含 <think> 推理块）
+    fn push_content<F: FnMut(StreamDelta)>(&mut self, text: &str, on_delta: &mut F) {
+        let (visible, reasoning) = self.splitter.push(text);
+        self.emit(&visible, &reasoning, on_delta);
+    }
+
+    /// 后端单独下发的思考过程（Ollama 的 thinking 字段）
+    fn push_reasoning<F: FnMut(StreamDelta)>(&mut self, text: &str, on_delta: &mut F) {
+        self.emit("", text, on_delta);
+    }
+
+    /// 流结束，返回（正文, 思考过程）
+    fn finish<F: FnMut(StreamDelta)>(mut self, on_delta: &mut F) -> (String, Option<String>) {
+        let (visible, reasoning) = self.splitter.finish();
+        self.emit(&visible, &reasoning, on_delta);
+        let reasoning = self.reasoning.trim();
+        (
+            self.content.trim_end().to_string(),
+            (!reasoning.is_empty()).then(|| reasoning.to_string()),
+        )
+    }
+
+    fn emit<F: FnMut(StreamDelta)>(&mut self, visible: &str, reasoning: &str, on_delta: &mut F) {
+        if !reasoning.is_empty() {
+            on_delta(StreamDelta::Reasoning(reasoning));
+            self.reasoning.push_str(reasoning);
+        }
+        if !visible.is_empty() {
+            on_delta(StreamDelta::Content(visible));
+            self.content.push_str(visible);
+        }
+    }
+}
+
+/// 构造 API 错误，上下文超长时包装为 ContextOverflowError 以便识别
+fn api_error(prefix: &str, status: reqwest::StatusCode, text: String) -> anyhow::Error {
+    let message = format!("{}：{} - {}", prefix, status, text);
+    if looks_like_context_overflow(status, &text) {
+        anyhow::Error::new(ContextOverflowError(message))
+    } else {
+        anyhow::Error::new(HttpStatusError { status, message })
+    }
+}
+
+/// Ollama 返回的 HTTP 错误，模型未拉取时包装为 ModelNotFoundError
+fn ollama_api_error(model: &str, status: reqwest::StatusCode, text: String) -> anyhow::Error {
+    if looks_like_model_not_found(&text) {
+        anyhow::Error::new(ModelNotFoundError(model.to_string()))
+    } else {
+        api_error("Ollama API 错误", status, text)
+    }
+}
+
+/// Ollama 在响应体 error 字段中返回的错误
+fn ollama_error(model: &str, err: String) -> anyhow::Error {
+    let message = format!("Ollama 错误：{}", err);
+    if looks_like_model_not_found(&err) {
+        anyhow::Error::new(ModelNotFoundError(model.to_string()))
+    } else if looks_like_context_overflow(reqwest::StatusCode::OK, &err) {
+        anyhow::Error::new(ContextOverflowError(message))
+    } else {
+        anyhow::anyhow!(message)
+    }
+}
+
+/// 读取完整响应体，超过 max_bytes 时报错
+async fn read_body(mut response: Response, max_bytes: usize) -> Result<String> {
+    check_content_length(&response, max_bytes)?;
+
+    let mut body: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await.context("读取响应失败")? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(response_too_large(max_bytes));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// 按行读取响应体，不完整的行留在缓冲区；回调返回 true 表示流结束
+async fn read_lines<F>(mut response: Response, max_bytes: usize, mut handle: F) -> Result<()>
+where
+    F: FnMut(&str) -> Result<bool>,
+{
+    check_content_length(&response, max_bytes)?;
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut total = 0;
+
+    while let Some(chunk) = response.chunk().await.context("读取流式响应失败")? {
+        total += chunk.len();
+        if total > max_bytes {
+            return Err(response_too_large(max_bytes));
+        }
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if !line.is_empty() && handle(line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // 处理末尾没有换行符的最后一块
+    let line = String::from_utf8_lossy(&buffer);
+    let line = line.trim();
+    if !line.is_empty() {
+        handle(line)?;
+    }
+
+    Ok(())
+}
+
+/// 服务端声明的长度已超限时直接拒绝，不读取响应体
+fn check_content_length(response: &Response, max_bytes: usize) -> Result<()> {
+    match response.content_length() {
+        Some(len) if len > max_bytes as u64 => Err(response_too_large(max_bytes)),
+        _ => Ok(()),
+    }
+}
+
+fn response_too_large(max_bytes: usize) -> anyhow::Error {
+    anyhow::anyhow!(
+        "LLM 响应超过大小限制（{} 字节），可通过 max_response_bytes 调整",
+        max_bytes
+    )
+}
+
+/// 备用模型也失败时的错误说明（保留主模型的错误信息）
+fn fallback_failed(model: &str, fallback: &str, error: &anyhow::Error) -> String {
+    format!("模型 {} 调用失败（{}），备用模型 {} 也调用失败", model, error, fallback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 本地 HTTP 服务：按顺序返回给定的原始响应（用完后重复最后一个），返回地址和请求计数
+    async fn mock_server(responses: Vec<String>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                let response = responses[index.min(responses.len() - 1)].clone();
+                tokio::spawn(async move {
+                    read_request(&mut socket).await;
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        (url, hits)
+    }
+
+    /// 读完请求头和 Content-Length 指定的请求体
+    async fn read_request(socket: &mut tokio::net::TcpStream) {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let Ok(n) = socket.read(&mut buf).await else { return };
+            if n == 0 {
+                return;
+            }
+            data.extend_from_slice(&buf[..n]);
+            let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") else { continue };
+            let headers = String::from_utf8_lossy(&data[..end]).to_lowercase();
+            let length = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if data.len() >= end + 4 + length {
+                return;
+            }
+        }
+    }
+
+    /// 带 Content-Length 的响应
+    fn http_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// 不带 Content-Length、读到连接关闭为止的响应
+    fn unsized_response(body: &str) -> String {
+        format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n{}", body)
+    }
+
+    async fn get(url: &str) -> Response {
+        Client::new().get(url).send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_body_rejects_declared_oversized_body() {
+        let (url, _) = mock_server(vec![http_response("200 OK", &"x".repeat(2048))]).await;
+        let error = read_body(get(&url).await, 1024).await.unwrap_err();
+        assert!(error.to_string().contains("LLM 响应超过大小限制（1024 字节）"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn read_body_rejects_oversized_body_without_content_length() {
+        let (url, _) = mock_server(vec![unsized_response(&"x".repeat(2048))]).await;
+        let error = read_body(get(&url).await, 1024).await.unwrap_err();
+        assert!(error.to_string().contains("LLM 响应超过大小限制（1024 字节）"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn read_body_accepts_body_at_limit() {
+        let (url, _) = mock_server(vec![http_response("200 OK", &"x".repeat(1024))]).await;
+        assert_eq!(read_body(get(&url).await, 1024).await.unwrap().len(), 1024);
+    }
+
+    #[tokio::test]
+    async fn read_lines_rejects_oversized_stream() {
+        let line = format!("{}\n", "y".repeat(99));
+        let (url, _) = mock_server(vec![unsized_response(&line.repeat(20))]).await;
+        let mut lines = 0;
+        let error = read_lines(get(&url).await, 1024, |_| {
+            lines += 1;
+            Ok(false)
+        })
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("LLM 响应超过大小限制（1024 字节）"), "{}", error);
+        assert!(lines <= 10);
+    }
+
+    #[tokio::test]
+    async fn chat_reports_oversized_response() {
+        let (url, _) = mock_server(vec![http_response("200 OK", &"x".repeat(4096))]).await;
+        let config = AgentConfig {
+            base_url: url,
+            stream: false,
+            max_llm_retries: 1,
+            max_response_bytes: 1024,
+            ..AgentConfig::default()
+        };
+        let client = LlmClient::new(config, Client::new());
+        let error = client.chat_with_retry(&[], None, false).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("LLM 响应超过大小限制"), "{:#}", error);
+    }
+
+    /// 最多尝试 3 次、重试间隔 1 ms 的客户端
+    fn retrying_client(base_url: String) -> LlmClient {
+        let config = AgentConfig {
+            base_url,
+            stream: false,
+            max_llm_retries: 3,
+            retry_base_ms: 1,
+            max_backoff_ms: 1,
+            fallback_model: None,
+            ..AgentConfig::default()
+        };
+        LlmClient::new(config, Client::new())
+    }
+
+    /// 错误链中的 HTTP 状态码
+    fn error_status(error: &anyhow::Error) -> Option<u16> {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<HttpStatusError>())
+            .map(|e| e.status.as_u16())
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        for status in ["400 Bad Request", "401 Unauthorized", "404 Not Found"] {
+            let (url, hits) = mock_server(vec![http_response(status, r#"{"error":"rejected"}"#)]).await;
+            let error = retrying_client(url).chat_with_retry(&[], None, false).await.unwrap_err();
+            assert_eq!(hits.load(Ordering::SeqCst), 1, "{}", status);
+            assert_eq!(error_status(&error), status[..3].parse().ok(), "{:#}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        for status in ["408 Request Timeout", "429 Too Many Requests", "500 Internal Server Error", "503 Service Unavailable"] {
+            let (url, hits) = mock_server(vec![http_response(status, r#"{"error":"busy"}"#)]).await;
+            let error = retrying_client(url).chat_with_retry(&[], None, false).await.unwrap_err();
+            assert_eq!(hits.load(Ordering::SeqCst), 3, "{}", status);
+            // 重试耗尽后仍能从错误链中取出原始状态码
+            assert_eq!(error_status(&error), status[..3].parse().ok(), "{:#}", error);
+            assert!(format!("{:#}", error).contains("LLM 调用在 3 次尝试后仍然失败"), "{:#}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_errors_are_retried() {
+        // 连接建立后不返回任何内容就关闭
+        let (url, hits) = mock_server(vec![String::new()]).await;
+        let error = retrying_client(url).chat_with_retry(&[], None, false).await.unwrap_err();
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(error.chain().any(|cause| cause.is::<reqwest::Error>()), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn retry_succeeds_after_transient_error() {
+        let reply = r#"{"message":{"role":"assistant","content":"ok"},"done":true}"#;
+        let (url, hits) = mock_server(vec![
+            http_response("503 Service Unavailable", "{}"),
+            http_response("200 OK", reply),
+        ])
+        .await;
+        let message = retrying_client(url).chat_with_retry(&[], None, false).await.unwrap();
+        assert_eq!(message.content, "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    fn backoff_config(retry_base_ms: u64, retry_multiplier: f64, max_backoff_ms: u64) -> AgentConfig {
+        AgentConfig { retry_base_ms, retry_multiplier, max_backoff_ms, ..AgentConfig::default() }
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let config = backoff_config(100, 2.0, 10_000);
+        let delays: Vec<_> = (1..=4).map(|attempt| retry_delay(&config, attempt).as_millis()).collect();
+        assert_eq!(delays, [200, 400, 800, 1600]);
+    }
+
+    #[test]
+    fn retry_delay_is_capped_for_huge_attempts_and_multipliers() {
+        let max = Duration::from_millis(30_000);
+        for multiplier in [2.0, 10.0, 1e10, f64::MAX] {
+            let config = backoff_config(500, multiplier, 30_000);
+            for attempt in [10, 1_000, i32::MAX as usize, usize::MAX] {
+                assert_eq!(retry_delay(&config, attempt), max, "multiplier {} attempt {}", multiplier, attempt);
+            }
+        }
+    }
+
+    #[test]
+    fn retry_delay_handles_extreme_limits() {
+        let config = backoff_config(u64::MAX, f64::MAX, u64::MAX);
+        assert_eq!(retry_delay(&config, usize::MAX), Duration::from_millis(u64::MAX));
+        assert_eq!(retry_delay(&backoff_config(u64::MAX, 2.0, 1_000), 1), Duration::from_millis(1_000));
+    }
+
+    /// 返回给定工具调用分片（index 和函数名）的 OpenAI 兼容流式响应
+    fn tool_call_stream(chunks: &[(usize, &str)]) -> String {
+        let mut body = String::new();
+        for (index, name) in chunks {
+            let chunk = serde_json::json!({
+                "choices": [{ "delta": { "tool_calls": [{
+                    "index": index,
+                    "id": format!("call-{}", index),
+                    "function": { "name": name, "arguments": "{}" }
+                }] } }]
+            });
+            body.push_str(&format!("data: {}\n\n", chunk));
+        }
+        body.push_str("data: [DONE]\n\n");
+        unsized_response(&body)
+    }
+
+    async fn stream_tool_calls(chunks: &[(usize, &str)]) -> Result<Message> {
+        let (url, _) = mock_server(vec![tool_call_stream(chunks)]).await;
+        let config = AgentConfig {
+            backend: Backend::OpenAiCompatible,
+            base_url: url,
+            max_tool_calls: 3,
+            ..AgentConfig::default()
+        };
+        LlmClient::new(config, Client::new()).chat_stream(&[], None, false, |_| {}).await
+    }
+
+    #[tokio::test]
+    async fn stream_assembles_sequential_tool_calls() {
+        let message = stream_tool_calls(&[(0, "fs_read"), (1, "fs_list")]).await.unwrap();
+        let names: Vec<_> = message.tool_calls.unwrap().into_iter().map(|tc| tc.function.name).collect();
+        assert_eq!(names, ["fs_read", "fs_list"]);
+    }
+
+    #[tokio::test]
+    async fn stream_rejects_huge_tool_call_index() {
+        let error = stream_tool_calls(&[(4_000_000_000, "fs_read")]).await.unwrap_err();
+        assert!(error.to_string().contains("工具调用序号无效：4000000000"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn stream_rejects_tool_call_index_gap() {
+        let error = stream_tool_calls(&[(0, "fs_read"), (2, "fs_list")]).await.unwrap_err();
+        assert!(error.to_string().contains("工具调用序号无效：2"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn stream_rejects_tool_calls_over_limit() {
+        let error = stream_tool_calls(&[(0, "a"), (1, "b"), (2, "c"), (3, "d")]).await.unwrap_err();
+        assert!(error.to_string().contains("上限 max_tool_calls 3"), "{}", error);
     }
 }
@@ -1,9 +1,13 @@
+pub mod audit;
 pub mod context;
 pub mod core;
 pub mod llm;
+pub mod reasoning;
 pub mod session;
+pub mod spinner;
 
-pub use core::Agent;
-pub use context::Context;
-pub use llm::LlmClient;
-pub use session::{Session, SessionManager};
+pub use audit::AuditLog;
+pub use core::{Agent, Approval, ApprovalCallback};
+pub use context::{CharTokenEstimator, Context, TokenEstimator};
+pub use llm::{LlmClient, OutputFormat, StreamDelta};
+pub use session::{LoadSummary, Session, SessionManager, SessionSummary};
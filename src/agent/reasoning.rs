@@ -0,0 +1,103 @@
+/// 推理块起始标签（qwen、deepseek 等推理模型在正文中输出）
+const OPEN_TAG: &str = "<think>";
+
+/// 推理块结束标签
+const CLOSE_TAG: &str = "</think>";
+
+/// 把回复中的 `<think>...</think>` 推理块与正文分开，返回（正文, 推理内容）
+pub fn split_reasoning(content: &str) -> (String, Option<String>) {
+    if !content.contains(OPEN_TAG) {
+        return (content.to_string(), None);
+    }
+
+    let mut splitter = ReasoningSplitter::default();
+    let (mut visible, mut reasoning) = splitter.push(content);
+    let (rest_visible, rest_reasoning) = splitter.finish();
+    visible.push_str(&rest_visible);
+    reasoning.push_str(&rest_reasoning);
+
+    let reasoning = reasoning.trim();
+    (visible.trim_end().to_string(), (!reasoning.is_empty()).then(|| reasoning.to_string()))
+}
+
+/// 合并后端单独返回的推理内容（如 Ollama 的 thinking 字段）和正文中的推理块
+pub fn merge_reasoning(native: Option<String>, inline: Option<String>) -> Option<String> {
+    let merged = [native, inline]
+        .into_iter()
+        .flatten()
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    (!merged.is_empty()).then_some(merged)
+}
+
+/// 流式拆分推理块：标签可能被切在两个增量之间，不完整的标签前缀暂存到下一次
+#[derive(Default)]
+pub struct ReasoningSplitter {
+    in_reasoning: bool,
+    pending: String,
+    /// 正文是否已输出非空白内容（推理块之后的前导空行不输出）
+    visible_started: bool,
+}
+
+impl ReasoningSplitter {
+    /// 处理一段增量，返回其中的（正文, 推理）部分
+    pub fn push(&mut self, delta: &str) -> (String, String) {
+        self.pending.push_str(delta);
+        let mut visible = String::new();
+        let mut reasoning = String::new();
+
+        loop {
+            let tag = if self.in_reasoning { CLOSE_TAG } else { OPEN_TAG };
+            let (emit, consumed) = match self.pending.find(tag) {
+                Some(pos) => (pos, pos + tag.len()),
+                None => {
+                    let emit = self.pending.len() - partial_tag_len(&self.pending, tag);
+                    (emit, emit)
+                }
+            };
+
+            let text = self.pending[..emit].to_string();
+            self.pending.drain(..consumed);
+            if self.in_reasoning {
+                reasoning.push_str(&text);
+            } else {
+                self.push_visible(&mut visible, &text);
+            }
+
+            if consumed == emit {
+                return (visible, reasoning);
+            }
+            self.in_reasoning = !self.in_reasoning;
+        }
+    }
+
+    /// 流结束时取出暂存内容（未闭合的推理块按推理处理）
+    pub fn finish(&mut self) -> (String, String) {
+        let rest = std::mem::take(&mut self.pending);
+        if self.in_reasoning {
+            (String::new(), rest)
+        } else {
+            let mut visible = String::new();
+            self.push_visible(&mut visible, &rest);
+            (visible, String::new())
+        }
+    }
+
+    fn push_visible(&mut self, visible: &mut String, text: &str) {
+        let text = if self.visible_started { text } else { text.trim_start() };
+        if !text.is_empty() {
+            self.visible_started = true;
+            visible.push_str(text);
+        }
+    }
+}
+
+/// 文本末尾与标签开头重合的长度（可能是被切开的标签）
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&n| text.ends_with(&tag[..n]))
+        .unwrap_or(0)
+}
@@ -2,13 +2,25 @@ use anyhow::{Result, Context as AnyhowContext};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
-use crate::config::AgentConfig;
-use crate::types::Message;
+use crate::config::{AgentConfig, SessionFormat, WorkspaceConfig};
+use crate::types::{FunctionCall, Message, TokenUsage, ToolCall};
 
 use super::context::Context as AgentContext;
 
+/// 批量导出时的清单文件名
+pub const EXPORT_MANIFEST: &str = "manifest.json";
+
+/// `session repair` 移走无法解析的会话文件时附加的后缀
+pub const CORRUPT_SUFFIX: &str = "corrupt";
+
+/// 会话文件的扩展名（JSON 和 MessagePack），加载时据此识别格式
+const SESSION_EXTENSIONS: &[&str] = &["json", "msgpack"];
+
 /// 会话数据（用于序列化）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SessionData {
@@ -19,6 +31,10 @@ struct SessionData {
     created_at: String,
     updated_at: String,
     name: Option<String>,
+    #[serde(default)]
+    total_prompt_tokens: u64,
+    #[serde(default)]
+    total_completion_tokens: u64,
 }
 
 /// 会话元数据
@@ -28,6 +44,62 @@ pub struct SessionMetadata {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub message_count: usize,
+    /// 累计输入 token 数（后端返回的计数）
+    pub total_prompt_tokens: u64,
+    /// 累计输出 token 数（后端返回的计数）
+    pub total_completion_tokens: u64,
+}
+
+/// 会话摘要（用于 JSON 输出，时间为 RFC3339 格式）
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub name: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub message_count: usize,
+    pub total_prompt_tokens: u64,
+    pub total_completion_tokens: u64,
+}
+
+impl SessionMetadata {
+    pub fn summary(&self, id: &str) -> SessionSummary {
+        SessionSummary {
+            id: id.to_string(),
+            name: self.name.clone(),
+            created_at: self.created_at.to_rfc3339(),
+            updated_at: self.updated_at.to_rfc3339(),
+            message_count: self.message_count,
+            total_prompt_tokens: self.total_prompt_tokens,
+            total_completion_tokens: self.total_completion_tokens,
+        }
+    }
+}
+
+/// `load_all` 的结果：成功加载的会话数和加载失败的会话文件
+#[derive(Debug, Default)]
+pub struct LoadSummary {
+    pub loaded: usize,
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+impl LoadSummary {
+    /// 有文件加载失败时的提示（失败数量和文件名）
+    pub fn warning(&self) -> Option<String> {
+        if self.failed.is_empty() {
+            return None;
+        }
+        let files: Vec<String> = self
+            .failed
+            .iter()
+            .map(|(path, e)| format!("  {}：{}", file_name(path), e.root_cause()))
+            .collect();
+        Some(format!(
+            "{} 个会话文件加载失败（可运行 rox session repair 移走无法解析的文件）：\n{}",
+            self.failed.len(),
+            files.join("\n")
+        ))
+    }
 }
 
 /// 会话
@@ -39,11 +111,10 @@ pub struct Session {
 }
 
 impl Session {
-    pub fn new(id: String, config: AgentConfig, _workspace_root: &Path) -> Self {
+    pub fn new(id: String, config: AgentConfig, workspace: &WorkspaceConfig) -> Self {
         let mut context = AgentContext::new(String::new());
         // 从 workspace 加载系统提示
-        let workspace_config = crate::config::WorkspaceConfig::default();
-        let _ = context.load_system_prompt(&workspace_config);
+        let _ = context.load_system_prompt(workspace);
 
         let now = Utc::now();
         Session {
@@ -55,6 +126,8 @@ impl Session {
                 created_at: now,
                 updated_at: now,
                 message_count: 0,
+                total_prompt_tokens: 0,
+                total_completion_tokens: 0,
             },
         }
     }
@@ -79,39 +152,271 @@ impl Session {
         &self.metadata
     }
 
+    /// 切换会话使用的模型
+    pub fn set_model(&mut self, model: &str) {
+        self.config.model = model.to_string();
+        self.metadata.updated_at = Utc::now();
+    }
+
+    /// 消息变化后刷新更新时间和消息数
+    pub fn touch(&mut self) {
+        self.metadata.message_count = self.context.len();
+        self.metadata.updated_at = Utc::now();
+    }
+
+    /// 累加一次请求的 token 用量
+    pub fn add_usage(&mut self, usage: TokenUsage) {
+        self.metadata.total_prompt_tokens += usage.prompt_tokens;
+        self.metadata.total_completion_tokens += usage.completion_tokens;
+    }
+
     pub fn rename(&mut self, name: &str) {
         self.metadata.name = Some(name.to_string());
         self.metadata.updated_at = Utc::now();
     }
 
-    /// 保存到文件
-    pub fn save(&self, storage_path: &Path) -> Result<()> {
-        let data = SessionData {
+    /// 提取用户输入及其对应的最终回复（每轮最后一条不含工具调用的 assistant 消息）
+    pub fn user_turns(&self) -> Vec<(String, Option<String>)> {
+        let mut turns: Vec<(String, Option<String>)> = Vec::new();
+        for msg in self.context.raw_messages() {
+            match msg.role.as_str() {
+                "user" => turns.push((msg.content.clone(), None)),
+                "assistant" if msg.tool_calls.is_none() => {
+                    if let Some(turn) = turns.last_mut() {
+                        turn.1 = Some(msg.content.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        turns
+    }
+
+    /// 导出为 Markdown（默认不含系统提示）
+    pub fn export_markdown(&self, include_system: bool) -> String {
+        let mut out = String::new();
+
+        let title = self.metadata.name.as_deref().unwrap_or("未命名会话");
+        out.push_str(&format!("# {}\n\n", title));
+        out.push_str(&format!("- 会话 ID：`{}`\n", self.id));
+        out.push_str(&format!("- 模型：`{}`\n", self.config.model));
+        out.push_str(&format!("- 创建时间：{}\n", self.metadata.created_at.to_rfc3339()));
+        out.push_str(&format!("- 更新时间：{}\n\n", self.metadata.updated_at.to_rfc3339()));
+
+        if include_system && !self.context.system_prompt().is_empty() {
+            out.push_str("## ⚙️ 系统提示\n\n");
+            out.push_str(&fenced(self.context.system_prompt(), "markdown"));
+        }
+
+        for msg in self.context.raw_messages() {
+            // 标题后附加消息时间（旧会话的消息没有时间）
+            let time = msg.timestamp.as_deref().map(|t| format!("{}{}", TIMESTAMP_SEPARATOR, t)).unwrap_or_default();
+            match msg.role.as_str() {
+                "user" => {
+                    out.push_str(&format!("## 👤 用户{}\n\n", time));
+                    out.push_str(&format!("{}\n\n", msg.content.trim()));
+                }
+                "assistant" => {
+                    out.push_str(&format!("## 🤖 助手{}\n\n", time));
+                    if !msg.content.trim().is_empty() {
+                        out.push_str(&format!("{}\n\n", msg.content.trim()));
+                    }
+                    for tc in msg.tool_calls.iter().flatten() {
+                        out.push_str(&format!("调用工具 `{}`：\n\n", tc.function.name));
+                        let args = match &tc.function.arguments {
+                            serde_json::Value::String(s) => s.clone(),
+                            other => serde_json::to_string_pretty(other).unwrap_or_default(),
+                        };
+                        out.push_str(&fenced(&args, "json"));
+                    }
+                }
+                "tool" => {
+                    out.push_str(&format!("## 🔧 工具结果{}\n\n", time));
+                    out.push_str(&fenced(&msg.content, ""));
+                }
+                _ => {}
+            }
+        }
+
+        out
+    }
+
+    /// 序列化为会话 JSON（与默认的会话文件格式相同，可通过 import 导入）
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_data())?)
+    }
+
+    /// 按存储格式序列化
+    pub fn to_bytes(&self, format: SessionFormat) -> Result<Vec<u8>> {
+        let data = self.to_data();
+        Ok(match format {
+            SessionFormat::JsonPretty => serde_json::to_vec_pretty(&data)?,
+            SessionFormat::JsonCompact => serde_json::to_vec(&data)?,
+            // 按字段名编码，新增的 `#[serde(default)]` 字段仍可兼容旧文件
+            SessionFormat::Messagepack => rmp_serde::to_vec_named(&data)?,
+        })
+    }
+
+    fn to_data(&self) -> SessionData {
+        SessionData {
             id: self.id.clone(),
             system_prompt: self.context.system_prompt().to_string(),
             messages: self.context.raw_messages().to_vec(),
-            config: self.config.clone(),
+            config: AgentConfig {
+                // 凭据只保存在配置文件中
+                api_key: None,
+                headers: Default::default(),
+                ..self.config.clone()
+            },
             created_at: self.metadata.created_at.to_rfc3339(),
             updated_at: self.metadata.updated_at.to_rfc3339(),
             name: self.metadata.name.clone(),
-        };
+            total_prompt_tokens: self.metadata.total_prompt_tokens,
+            total_completion_tokens: self.metadata.total_completion_tokens,
+        }
+    }
 
+    /// 按存储格式保存到 `<ID>.<扩展名>`，并删除该会话其他格式的旧文件，返回写入的路径
+    pub fn save(&self, storage_path: &Path, format: SessionFormat) -> Result<PathBuf> {
         if let Some(parent) = storage_path.parent() {
             fs::create_dir_all(parent)?;
         }
 
-        let path = storage_path.join(format!("{}.json", self.id));
-        fs::write(path, serde_json::to_string_pretty(&data)?)?;
-        Ok(())
+        let path = storage_path.join(format!("{}.{}", self.id, format.extension()));
+        fs::write(&path, self.to_bytes(format)?)
+            .with_context(|| format!("写入会话文件失败：{}", path.display()))?;
+
+        // 切换格式后旧文件不再更新，留着会在下次加载时与新文件重复
+        for extension in SESSION_EXTENSIONS.iter().filter(|ext| **ext != format.extension()) {
+            let _ = fs::remove_file(storage_path.join(format!("{}.{}", self.id, extension)));
+        }
+        Ok(path)
     }
 
-    /// 从文件加载
+    /// 从文件加载，按扩展名识别格式（`.msgpack` 为 MessagePack，其余按 JSON 解析）
     pub fn load(path: &Path) -> Result<Self> {
-        let content = fs::read_to_string(path)
+        let content = fs::read(path)
             .with_context(|| format!("加载会话文件失败：{}", path.display()))?;
-        let data: SessionData = serde_json::from_str(&content)
-            .with_context(|| format!("解析会话文件失败：{}", path.display()))?;
+        let data: SessionData = if path.extension().is_some_and(|ext| ext == "msgpack") {
+            rmp_serde::from_slice(&content)
+                .with_context(|| format!("解析会话文件失败：{}", path.display()))?
+        } else {
+            serde_json::from_slice(&content)
+                .with_context(|| format!("解析会话文件失败：{}", path.display()))?
+        };
+        Ok(Session::from_data(data))
+    }
+
+    /// 从会话 JSON 或 Markdown 导出文件导入，分配新的会话 ID 以免与已有会话冲突；
+    /// Markdown 中未记录模型时使用 `config`，未记录系统提示时从 `workspace` 加载
+    pub fn import(path: &Path, config: AgentConfig, workspace: &WorkspaceConfig) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("读取导入文件失败：{}", path.display()))?;
+
+        let mut session = if content.trim_start().starts_with('{') {
+            let data: SessionData = serde_json::from_str(&content)
+                .with_context(|| format!("解析会话 JSON 失败：{}", path.display()))?;
+            Session::from_data(data)
+        } else {
+            Session::from_markdown(&content, config, workspace)
+                .with_context(|| format!("解析会话 Markdown 失败：{}", path.display()))?
+        };
+
+        session.id = uuid::Uuid::new_v4().to_string();
+        Ok(session)
+    }
+
+    /// 解析 `export_markdown` 生成的 Markdown
+    fn from_markdown(markdown: &str, mut config: AgentConfig, workspace: &WorkspaceConfig) -> Result<Self> {
+        let mut name = None;
+        let mut created_at = None;
+        let mut updated_at = None;
+        let mut system_prompt = None;
+        let mut messages: Vec<Message> = Vec::new();
+        let mut has_title = false;
+
+        for (header, timestamp, body) in markdown_sections(markdown) {
+            match header {
+                None => {
+                    for line in body.lines() {
+                        if let Some(title) = line.strip_prefix("# ") {
+                            has_title = true;
+                            if title.trim() != "未命名会话" {
+                                name = Some(title.trim().to_string());
+                            }
+                        } else if let Some(model) = line.strip_prefix("- 模型：") {
+                            config.model = model.trim().trim_matches('`').to_string();
+                        } else if let Some(time) = line.strip_prefix("- 创建时间：") {
+                            created_at = parse_time(time);
+                        } else if let Some(time) = line.strip_prefix("- 更新时间：") {
+                            updated_at = parse_time(time);
+                        }
+                    }
+                }
+                Some(MarkdownRole::System) => {
+                    system_prompt = Some(unfence(&body).context("系统提示缺少代码块")?);
+                }
+                Some(MarkdownRole::User) => messages.push(Message {
+                    role: "user".to_string(),
+                    content: body.trim().to_string(),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    reasoning: None,
+                    images: None,
+                    usage: None,
+                    timestamp,
+                }),
+                Some(MarkdownRole::Assistant) => messages.push(Message {
+                    timestamp,
+                    ..parse_assistant_section(&body, messages.len())?
+                }),
+                Some(MarkdownRole::Tool) => {
+                    let content = unfence(&body).context("工具结果缺少代码块")?;
+                    let answered = messages.iter().rev().take_while(|m| m.role == "tool").count();
+                    let tool_call_id = messages
+                        .iter()
+                        .rev()
+                        .find(|m| m.role != "tool")
+                        .and_then(|m| m.tool_calls.as_ref())
+                        .and_then(|calls| calls.get(answered))
+                        .map(|tc| tc.id.clone())
+                        .context("工具结果缺少对应的工具调用")?;
+                    messages.push(Message {
+                        role: "tool".to_string(),
+                        content,
+                        tool_calls: None,
+                        tool_call_id: Some(tool_call_id),
+                        reasoning: None,
+                        images: None,
+                        usage: None,
+                        timestamp,
+                    });
+                }
+            }
+        }
+
+        if !has_title {
+            return Err(anyhow::anyhow!("缺少标题行（# 会话名称）"));
+        }
+        if messages.is_empty() {
+            return Err(anyhow::anyhow!("未找到任何消息（## 👤 用户 / ## 🤖 助手）"));
+        }
+
+        let mut session = Session::new(String::new(), config, workspace);
+        if let Some(prompt) = system_prompt {
+            session.context = AgentContext::new(prompt);
+        }
+        *session.context.raw_messages_mut() = messages;
+        session.metadata.name = name;
+        session.metadata.message_count = session.context.len();
+        if let Some(time) = created_at {
+            session.metadata.created_at = time;
+        }
+        session.metadata.updated_at = updated_at.unwrap_or(session.metadata.created_at);
+        Ok(session)
+    }
 
+    fn from_data(data: SessionData) -> Self {
         let mut context = AgentContext::new(data.system_prompt);
         for msg in data.messages {
             context.raw_messages_mut().push(msg);
@@ -119,7 +424,7 @@ impl Session {
 
         let message_count = context.len();
 
-        Ok(Session {
+        Session {
             id: data.id,
             context,
             config: data.config,
@@ -132,8 +437,10 @@ impl Session {
                     .map(|d| d.with_timezone(&Utc))
                     .unwrap_or_else(|_| Utc::now()),
                 message_count,
+                total_prompt_tokens: data.total_prompt_tokens,
+                total_completion_tokens: data.total_completion_tokens,
             },
-        })
+        }
     }
 }
 
@@ -142,35 +449,109 @@ pub struct SessionManager {
     sessions: HashMap<String, Session>,
     storage_path: PathBuf,
     current_session_id: Option<String>,
+    /// 自动保存失败的警告是否已显示（避免每轮重复提示）
+    save_warning_shown: bool,
+    /// 是否启用自动保存（对应配置 session.auto_save）
+    auto_save_enabled: bool,
+    /// 保存会话时使用的格式（对应配置 session.format）
+    format: SessionFormat,
+    /// 新会话从中加载系统提示的 workspace 配置
+    workspace: WorkspaceConfig,
 }
 
 use std::collections::HashMap;
 
 impl SessionManager {
-    pub fn new(storage_path: PathBuf) -> Self {
+    pub fn new(storage_path: PathBuf, workspace: WorkspaceConfig) -> Self {
         SessionManager {
             sessions: HashMap::new(),
             storage_path,
             current_session_id: None,
+            save_warning_shown: false,
+            auto_save_enabled: true,
+            format: SessionFormat::default(),
+            workspace,
         }
     }
 
+    /// 设置是否启用自动保存
+    pub fn set_auto_save(&mut self, enabled: bool) {
+        self.auto_save_enabled = enabled;
+    }
+
+    /// 设置保存会话时使用的格式
+    pub fn set_format(&mut self, format: SessionFormat) {
+        self.format = format;
+    }
+
+    /// 会话在存储目录中已有的文件（各格式）
+    fn session_files(&self, id: &str) -> Vec<PathBuf> {
+        SESSION_EXTENSIONS
+            .iter()
+            .map(|ext| self.storage_path.join(format!("{}.{}", id, ext)))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
     /// 创建新会话
     pub fn create(&mut self, name: Option<String>, config: AgentConfig) -> &Session {
         let id = uuid::Uuid::new_v4().to_string();
-        let workspace_config = crate::config::WorkspaceConfig::default();
-        let mut session = Session::new(id.clone(), config, &workspace_config.root);
+        self.insert_new(id, name, config)
+    }
+
+    /// 使用指定 ID 创建会话并设为当前会话，便于测试和脚本得到可复现的会话文件；
+    /// ID 作为文件名，只能包含字母、数字、`-` 和 `_`
+    pub fn create_with_id(&mut self, id: &str, name: Option<String>, config: AgentConfig) -> Result<&Session> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(anyhow::anyhow!("会话 ID 只能包含字母、数字、- 和 _：{}", id));
+        }
+        if self.sessions.contains_key(id) || !self.session_files(id).is_empty() {
+            return Err(anyhow::anyhow!("会话 ID 已存在：{}", id));
+        }
+        Ok(self.insert_new(id.to_string(), name, config))
+    }
+
+    fn insert_new(&mut self, id: String, name: Option<String>, config: AgentConfig) -> &Session {
+        let mut session = Session::new(id.clone(), config, &self.workspace);
 
         if let Some(name) = name {
             session.rename(&name);
         }
 
         self.sessions.insert(id.clone(), session);
-        self.current_session_id = Some(id.clone());
+        self.set_current(&id);
         // 安全：刚插入的 key 一定存在
         self.sessions.get(&id).unwrap()
     }
 
+    /// 复制会话（消息历史和配置）为新会话并设为当前会话，返回新会话 ID；原会话不受影响
+    pub fn fork(&mut self, id: &str) -> Result<String> {
+        let source = self.sessions.get(id)
+            .ok_or_else(|| anyhow::anyhow!("会话不存在：{}", id))?;
+        let short_id = if id.len() > 8 { &id[..8] } else { id };
+        let name = format!("{} 的分支", source.metadata.name.as_deref().unwrap_or(short_id));
+
+        let now = Utc::now();
+        let fork_id = uuid::Uuid::new_v4().to_string();
+        let session = Session {
+            id: fork_id.clone(),
+            context: source.context.clone(),
+            config: source.config.clone(),
+            metadata: SessionMetadata {
+                name: Some(name),
+                created_at: now,
+                updated_at: now,
+                message_count: source.metadata.message_count,
+                total_prompt_tokens: 0,
+                total_completion_tokens: 0,
+            },
+        };
+
+        self.sessions.insert(fork_id.clone(), session);
+        self.set_current(&fork_id);
+        Ok(fork_id)
+    }
+
     /// 获取会话
     pub fn get(&self, id: &str) -> Option<&Session> {
         self.sessions.get(id)
@@ -200,22 +581,67 @@ impl SessionManager {
             .and_then(|id| self.sessions.get_mut(&id))
     }
 
+    /// 按完整 ID 或唯一前缀查找会话 ID
+    pub fn resolve_id(&self, prefix: &str) -> Result<String> {
+        if self.sessions.contains_key(prefix) {
+            return Ok(prefix.to_string());
+        }
+
+        let matches: Vec<&String> = self.sessions
+            .keys()
+            .filter(|id| id.starts_with(prefix))
+            .collect();
+
+        match matches.len() {
+            0 => Err(anyhow::anyhow!("会话不存在：{}", prefix)),
+            1 => Ok(matches[0].clone()),
+            n => Err(anyhow::anyhow!("ID 前缀 {} 匹配到 {} 个会话，请提供更长的前缀", prefix, n)),
+        }
+    }
+
     /// 切换会话
     pub fn switch(&mut self, id: &str) -> bool {
-        if self.sessions.contains_key(id) {
-            self.current_session_id = Some(id.to_string());
-            true
-        } else {
-            false
+        self.set_current(id)
+    }
+
+    /// 设置当前会话并写入 current.txt，下次启动时恢复
+    pub fn set_current(&mut self, id: &str) -> bool {
+        if !self.sessions.contains_key(id) {
+            return false;
         }
+
+        self.current_session_id = Some(id.to_string());
+        // 持久化失败不影响本次运行，仅下次启动无法恢复
+        let _ = fs::create_dir_all(&self.storage_path)
+            .and_then(|_| fs::write(self.current_file(), id));
+        true
+    }
+
+    /// 清除当前会话并删除 current.txt，下次启动时选择最近更新的会话
+    pub fn clear_current(&mut self) {
+        self.current_session_id = None;
+        let _ = fs::remove_file(self.current_file());
+    }
+
+    /// 记录当前会话 ID 的文件
+    fn current_file(&self) -> PathBuf {
+        self.storage_path.join("current.txt")
     }
 
-    /// 删除会话
-    pub fn delete(&mut self, id: &str) -> bool {
+    /// 删除会话及其会话文件，返回会话是否存在
+    pub fn delete(&mut self, id: &str) -> Result<bool> {
         if self.current_session_id.as_deref() == Some(id) {
-            self.current_session_id = None;
+            self.clear_current();
         }
-        self.sessions.remove(id).is_some()
+        if self.sessions.remove(id).is_none() {
+            return Ok(false);
+        }
+
+        for path in self.session_files(id) {
+            fs::remove_file(&path)
+                .with_context(|| format!("删除会话文件失败：{}", path.display()))?;
+        }
+        Ok(true)
     }
 
     /// 列出所有会话
@@ -226,63 +652,517 @@ impl SessionManager {
             .collect()
     }
 
-    /// 保存会话
-    pub fn save(&self, id: &str) -> Result<()> {
+    /// 所有会话的摘要，按最近更新时间倒序排列
+    pub fn summaries(&self) -> Vec<SessionSummary> {
+        let mut sessions = self.list();
+        sessions.sort_by_key(|(_, metadata)| std::cmp::Reverse(metadata.updated_at));
+        sessions
+            .into_iter()
+            .map(|(id, metadata)| metadata.summary(id))
+            .collect()
+    }
+
+    /// 按内容搜索会话（不区分大小写的子串匹配），返回会话 ID、元数据和首个匹配片段，
+    /// 按最近更新时间倒序排列
+    pub fn search(&self, query: &str) -> Vec<(&str, &SessionMetadata, String)> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut results: Vec<_> = self
+            .sessions
+            .iter()
+            .filter_map(|(id, session)| {
+                session
+                    .context()
+                    .raw_messages()
+                    .iter()
+                    .find_map(|m| match_snippet(&m.content, &query))
+                    .map(|snippet| (id.as_str(), session.metadata(), snippet))
+            })
+            .collect();
+        results.sort_by_key(|(_, metadata, _)| std::cmp::Reverse(metadata.updated_at));
+        results
+    }
+
+    /// 保存会话，返回写入的路径
+    pub fn save(&self, id: &str) -> Result<PathBuf> {
         let session = self.sessions.get(id)
             .ok_or_else(|| anyhow::anyhow!("会话不存在：{}", id))?;
 
-        session.save(&self.storage_path)
+        session.save(&self.storage_path, self.format)
     }
 
-    /// 保存当前会话
-    pub fn save_current(&self) -> Result<()> {
+    /// 保存当前会话，返回写入的路径
+    pub fn save_current(&self) -> Result<PathBuf> {
         let id = self.current_session_id
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("没有当前会话"))?;
         self.save(id)
     }
 
+    /// 自动保存当前会话：失败时不中断对话，仅首次失败时提示用户
+    pub fn auto_save(&mut self) {
+        if let Some(session) = self.current_mut() {
+            session.touch();
+        }
+        if !self.auto_save_enabled {
+            return;
+        }
+        match self.save_current() {
+            Ok(_) => self.save_warning_shown = false,
+            Err(e) => {
+                if !self.save_warning_shown {
+                    self.save_warning_shown = true;
+                    ui_eprintln!("⚠️ 自动保存会话失败：{}", describe_save_error(&e));
+                    ui_eprintln!("   存储目录：{}", self.storage_path.display());
+                    ui_eprintln!("   对话仍保留在内存中，可使用 /save 重试或 /export <文件> 导出以免丢失\n");
+                }
+            }
+        }
+    }
+
+    /// 从 JSON 或 Markdown 文件导入会话并保存，返回新会话 ID（不切换当前会话）
+    pub fn import(&mut self, path: &Path, config: AgentConfig) -> Result<String> {
+        let session = Session::import(path, config, &self.workspace)?;
+        let id = session.id().to_string();
+        session.save(&self.storage_path, self.format)?;
+        self.sessions.insert(id.clone(), session);
+        Ok(id)
+    }
+
+    /// 导出全部会话到目录：每个会话一个 `<ID>.json`（可选附带 `<ID>.md`），
+    /// 并写入列出 ID、名称和时间的 `manifest.json`；目标以 `.zip` 结尾时打包为 zip 文件。
+    /// 返回导出的会话数
+    pub fn export_all(&self, target: &Path, include_markdown: bool) -> Result<usize> {
+        let summaries = self.summaries();
+        let mut files = Vec::new();
+        for summary in &summaries {
+            let session = &self.sessions[&summary.id];
+            files.push((format!("{}.json", summary.id), session.to_json()?));
+            if include_markdown {
+                files.push((format!("{}.md", summary.id), session.export_markdown(false)));
+            }
+        }
+        let manifest = serde_json::json!({
+            "exported_at": Utc::now().to_rfc3339(),
+            "sessions": summaries,
+        });
+        files.push((EXPORT_MANIFEST.to_string(), serde_json::to_string_pretty(&manifest)?));
+
+        let is_zip = target
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+        if is_zip {
+            write_zip(target, &files)?;
+        } else {
+            fs::create_dir_all(target)
+                .with_context(|| format!("创建导出目录失败：{}", target.display()))?;
+            for (name, content) in &files {
+                let path = target.join(name);
+                fs::write(&path, content)
+                    .with_context(|| format!("写入文件失败：{}", path.display()))?;
+            }
+        }
+
+        Ok(summaries.len())
+    }
+
     /// 加载会话
     pub fn load(&mut self, id: &str) -> Result<()> {
-        let path = self.storage_path.join(format!("{}.json", id));
-        let session = Session::load(&path)?;
-        self.sessions.insert(id.to_string(), session);
+        let path = self.session_files(id)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("会话文件不存在：{}", id))?;
+        self.load_file(id, &path)
+    }
+
+    /// 从指定文件加载会话；同一会话已有其他格式的文件时保留更新时间较新的一份
+    fn load_file(&mut self, id: &str, path: &Path) -> Result<()> {
+        let session = Session::load(path)?;
+        let is_newer = self.sessions
+            .get(id)
+            .is_none_or(|existing| session.metadata().updated_at > existing.metadata().updated_at);
+        if is_newer {
+            self.sessions.insert(id.to_string(), session);
+        }
         Ok(())
     }
 
-    /// 加载所有会话
-    pub fn load_all(&mut self) -> Result<()> {
+    /// 加载所有会话；单个文件加载失败不影响其他会话，失败的文件记录在返回的 `LoadSummary` 中
+    pub fn load_all(&mut self) -> Result<LoadSummary> {
+        let mut summary = LoadSummary::default();
         if !self.storage_path.exists() {
-            return Ok(());
+            return Ok(summary);
         }
 
-        let mut errors = Vec::new();
         for entry in fs::read_dir(&self.storage_path)? {
             let entry = entry?;
             let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
+            let is_session_file = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|ext| SESSION_EXTENSIONS.contains(&ext));
+            if is_session_file {
                 if let Some(id) = path.file_stem().and_then(|s| s.to_str()) {
-                    if let Err(e) = self.load(id) {
-                        errors.push(format!("加载会话 {} 失败：{}", id, e));
+                    match self.load_file(id, &path) {
+                        Ok(()) => summary.loaded += 1,
+                        Err(e) => summary.failed.push((path.clone(), e)),
                     }
                 }
             }
         }
+        summary.failed.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // 优先恢复上次使用的会话
+        if self.current_session_id.is_none() {
+            if let Ok(saved) = fs::read_to_string(self.current_file()) {
+                let saved = saved.trim();
+                if self.sessions.contains_key(saved) {
+                    self.current_session_id = Some(saved.to_string());
+                }
+            }
+        }
 
-        // 加载完成后，自动选择最近更新的会话作为当前会话
+        // 记录的会话不存在时，选择最近更新的会话作为当前会话
         if self.current_session_id.is_none() && !self.sessions.is_empty() {
             let mut sessions: Vec<_> = self.sessions.iter().collect();
-            sessions.sort_by(|a, b| b.1.metadata().updated_at.cmp(&a.1.metadata().updated_at));
+            sessions.sort_by_key(|s| std::cmp::Reverse(s.1.metadata().updated_at));
 
             if let Some((id, _)) = sessions.first() {
                 self.current_session_id = Some(id.to_string());
             }
         }
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("部分会话加载失败:\n{}", errors.join("\n")))
+        Ok(summary)
+    }
+
+    /// 把无法解析的会话文件重命名为 `<文件名>.corrupt` 备份（已存在时追加序号），返回（原路径, 备份路径）；
+    /// 因权限等原因读取失败的文件不移动
+    pub fn repair(&mut self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let summary = self.load_all()?;
+        let mut moved = Vec::new();
+        for (path, error) in &summary.failed {
+            let is_parse_error = error
+                .chain()
+                .any(|cause| cause.is::<serde_json::Error>() || cause.is::<rmp_serde::decode::Error>());
+            if !is_parse_error {
+                continue;
+            }
+            let mut backup = path.with_file_name(format!("{}.{}", file_name(path), CORRUPT_SUFFIX));
+            let mut n = 1;
+            while backup.exists() {
+                backup = path.with_file_name(format!("{}.{}.{}", file_name(path), n, CORRUPT_SUFFIX));
+                n += 1;
+            }
+            fs::rename(path, &backup)
+                .with_context(|| format!("移动会话文件失败：{}", path.display()))?;
+            moved.push((path.clone(), backup));
         }
+        Ok(moved)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// 区分保存失败的原因：权限问题需要用户处理，磁盘已满等可能是暂时性的
+fn describe_save_error(error: &anyhow::Error) -> String {
+    let io_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<std::io::Error>());
+
+    match io_error.map(|e| e.kind()) {
+        Some(ErrorKind::PermissionDenied) | Some(ErrorKind::ReadOnlyFilesystem) => {
+            format!("没有写入权限（需要检查目录权限）：{}", error)
+        }
+        Some(ErrorKind::StorageFull) | Some(ErrorKind::QuotaExceeded) => {
+            format!("磁盘空间不足（释放空间后会自动重试）：{}", error)
+        }
+        _ => format!("{}（下次对话时会自动重试）", error),
+    }
+}
+
+/// 用代码块包裹内容，内容中含有 ``` 时自动加长围栏
+fn fenced(content: &str, lang: &str) -> String {
+    let mut fence = "```".to_string();
+    while content.contains(&fence) {
+        fence.push('`');
+    }
+    format!("{}{}\n{}\n{}\n\n", fence, lang, content.trim_end(), fence)
+}
+
+/// Markdown 导出中的消息小节
+#[derive(Clone, Copy)]
+enum MarkdownRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl MarkdownRole {
+    /// 解析小节标题，返回角色和标题中附带的消息时间
+    fn from_header(line: &str) -> Option<(Self, Option<String>)> {
+        let (title, timestamp) = match line.trim_end().split_once(TIMESTAMP_SEPARATOR) {
+            Some((title, time)) => (title, Some(time.trim().to_string())),
+            None => (line.trim_end(), None),
+        };
+        let role = match title {
+            "## ⚙️ 系统提示" => MarkdownRole::System,
+            "## 👤 用户" => MarkdownRole::User,
+            "## 🤖 助手" => MarkdownRole::Assistant,
+            "## 🔧 工具结果" => MarkdownRole::Tool,
+            _ => return None,
+        };
+        Some((role, timestamp))
+    }
+}
+
+/// Markdown 导出中消息标题与消息时间之间的分隔符
+const TIMESTAMP_SEPARATOR: &str = " · ";
+
+/// 按消息小节标题切分 Markdown（代码块内的标题不切分），第一段为标题和元信息；
+/// 每段为（角色、消息时间、内容）
+fn markdown_sections(markdown: &str) -> Vec<(Option<MarkdownRole>, Option<String>, String)> {
+    let mut sections = vec![(None, None, String::new())];
+    let mut fence: Option<String> = None;
+
+    for line in markdown.lines() {
+        match &fence {
+            Some(open) if line.trim_end() == open => fence = None,
+            Some(_) => {}
+            None => {
+                if let Some((role, timestamp)) = MarkdownRole::from_header(line) {
+                    sections.push((Some(role), timestamp, String::new()));
+                    continue;
+                }
+                if line.starts_with("```") {
+                    fence = Some(line.chars().take_while(|c| *c == '`').collect());
+                }
+            }
+        }
+        // 安全：sections 初始即有一个元素
+        let body = &mut sections.last_mut().unwrap().2;
+        body.push_str(line);
+        body.push('\n');
+    }
+    sections
+}
+
+/// 取出被代码块包裹的内容（`fenced` 的逆操作）
+fn unfence(body: &str) -> Option<String> {
+    let body = body.trim();
+    let first = body.lines().next()?;
+    let fence: String = first.chars().take_while(|c| *c == '`').collect();
+    if fence.len() < 3 {
+        return None;
+    }
+
+    let inner: Vec<&str> = body.lines().skip(1).collect();
+    let (last, content) = inner.split_last()?;
+    if last.trim_end() != fence {
+        return None;
+    }
+    Some(content.join("\n"))
+}
+
+/// 解析助手小节：先是回复正文，之后是若干 "调用工具 `name`：" 加参数代码块
+fn parse_assistant_section(body: &str, index: usize) -> Result<Message> {
+    let mut content = Vec::new();
+    let mut tool_calls = Vec::new();
+    let mut lines = body.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let name = line
+            .strip_prefix("调用工具 `")
+            .and_then(|rest| rest.strip_suffix("`："));
+        let Some(name) = name else {
+            if tool_calls.is_empty() {
+                content.push(line);
+            }
+            continue;
+        };
+
+        let mut block = String::new();
+        while lines.peek().is_some_and(|l| l.trim().is_empty()) {
+            lines.next();
+        }
+        let open = lines.next().unwrap_or_default();
+        let fence: String = open.chars().take_while(|c| *c == '`').collect();
+        block.push_str(open);
+        block.push('\n');
+        for line in lines.by_ref() {
+            block.push_str(line);
+            block.push('\n');
+            if line.trim_end() == fence {
+                break;
+            }
+        }
+
+        let args = unfence(&block).with_context(|| format!("工具调用 {} 缺少参数代码块", name))?;
+        let arguments = serde_json::from_str(&args).unwrap_or(serde_json::Value::String(args));
+        tool_calls.push(ToolCall {
+            id: format!("imported_{}_{}", index, tool_calls.len()),
+            r#type: Some("function".to_string()),
+            function: FunctionCall {
+                name: name.to_string(),
+                index: None,
+                arguments,
+            },
+        });
+    }
+
+    Ok(Message {
+        role: "assistant".to_string(),
+        content: content.join("\n").trim().to_string(),
+        tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+        tool_call_id: None,
+        reasoning: None,
+        images: None,
+        usage: None,
+        timestamp: None,
+    })
+}
+
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// 搜索片段的最大字符数
+const SNIPPET_MAX_CHARS: usize = 80;
+
+/// 在消息内容中查找（已转为小写的）查询串，返回首个匹配行作为片段；
+/// 匹配逻辑集中在这里，便于以后扩展为正则匹配
+fn match_snippet(content: &str, query: &str) -> Option<String> {
+    let line = content
+        .lines()
+        .find(|line| line.to_lowercase().contains(query))?
+        .trim();
+
+    if line.chars().count() > SNIPPET_MAX_CHARS {
+        let truncated: String = line.chars().take(SNIPPET_MAX_CHARS).collect();
+        Some(format!("{}…", truncated))
+    } else {
+        Some(line.to_string())
+    }
+}
+
+/// 将（文件名, 内容）列表写入 zip 文件
+fn write_zip(path: &Path, files: &[(String, String)]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let archive = fs::File::create(path)
+        .with_context(|| format!("创建压缩包失败：{}", path.display()))?;
+    let mut zip = ZipWriter::new(archive);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, content) in files {
+        zip.start_file(name.as_str(), options)
+            .and_then(|_| zip.write_all(content.as_bytes()).map_err(Into::into))
+            .with_context(|| format!("写入压缩包失败：{}", name))?;
+    }
+    zip.finish().context("写入压缩包失败")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 带两条消息的会话；workspace 中没有提示文件，系统提示为空
+    fn sample_session(workspace_root: &Path) -> Session {
+        let workspace = WorkspaceConfig {
+            root: workspace_root.to_path_buf(),
+            ..WorkspaceConfig::default()
+        };
+        let mut session = Session::new("s1".to_string(), AgentConfig::default(), &workspace);
+        session.context_mut().add_user("你好");
+        session.context_mut().add_assistant("你好！有什么可以帮你？", None);
+        session.rename("测试会话");
+        session
+    }
+
+    /// 会话的全部可保存内容
+    fn saved_content(session: &Session) -> serde_json::Value {
+        serde_json::to_value(session.to_data()).unwrap()
+    }
+
+    fn assert_round_trip(format: SessionFormat) -> PathBuf {
+        let dir = tempfile::tempdir().unwrap();
+        let session = sample_session(dir.path());
+
+        let path = session.save(dir.path(), format).unwrap();
+        assert_eq!(path, dir.path().join(format!("s1.{}", format.extension())));
+        let loaded = Session::load(&path).unwrap();
+        assert_eq!(saved_content(&loaded), saved_content(&session));
+        assert_eq!(loaded.metadata().name.as_deref(), Some("测试会话"));
+        assert_eq!(loaded.metadata().message_count, 2);
+        path
+    }
+
+    #[test]
+    fn json_pretty_round_trip() {
+        assert_round_trip(SessionFormat::JsonPretty);
+    }
+
+    #[test]
+    fn json_compact_round_trip() {
+        assert_round_trip(SessionFormat::JsonCompact);
+    }
+
+    #[test]
+    fn messagepack_round_trip() {
+        assert_round_trip(SessionFormat::Messagepack);
+    }
+
+    #[test]
+    fn json_formats_differ_only_in_whitespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = sample_session(dir.path());
+        let pretty = session.to_bytes(SessionFormat::JsonPretty).unwrap();
+        let compact = session.to_bytes(SessionFormat::JsonCompact).unwrap();
+
+        assert!(pretty.contains(&b'\n'));
+        assert!(!compact.contains(&b'\n'));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&pretty).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(&compact).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_detects_format_from_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = sample_session(dir.path());
+        let msgpack = session.to_bytes(SessionFormat::Messagepack).unwrap();
+
+        fs::write(dir.path().join("a.msgpack"), &msgpack).unwrap();
+        assert_eq!(saved_content(&Session::load(&dir.path().join("a.msgpack")).unwrap()), saved_content(&session));
+
+        // 内容相同、扩展名不同时按 JSON 解析
+        fs::write(dir.path().join("a.json"), &msgpack).unwrap();
+        let error = Session::load(&dir.path().join("a.json")).err().unwrap();
+        assert!(error.to_string().contains("解析会话文件失败"), "{}", error);
+    }
+
+    #[test]
+    fn save_in_new_format_removes_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = sample_session(dir.path());
+        session.save(dir.path(), SessionFormat::JsonPretty).unwrap();
+        session.save(dir.path(), SessionFormat::Messagepack).unwrap();
+
+        assert!(!dir.path().join("s1.json").exists());
+        let mut manager = SessionManager::new(dir.path().to_path_buf(), WorkspaceConfig::default());
+        assert_eq!(manager.load_all().unwrap().loaded, 1);
+        assert_eq!(saved_content(manager.get("s1").unwrap()), saved_content(&session));
     }
 }
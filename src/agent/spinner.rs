@@ -0,0 +1,57 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+const FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 等待模型响应时在 stderr 显示的转圈提示，stop 或 drop 时清除该行
+pub struct Spinner {
+    /// 是否已停止；绘制和清除都在锁内进行，停止后不会再输出
+    stopped: Arc<Mutex<bool>>,
+    task: JoinHandle<()>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        let stopped = Arc::new(Mutex::new(false));
+        let task = tokio::spawn({
+            let stopped = stopped.clone();
+            let message = message.to_string();
+            async move {
+                for frame in FRAMES.iter().cycle() {
+                    {
+                        let stopped = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+                        if *stopped {
+                            break;
+                        }
+                        eprint!("\r{} {}", frame, message);
+                        let _ = std::io::stderr().flush();
+                    }
+                    tokio::time::sleep(FRAME_INTERVAL).await;
+                }
+            }
+        });
+        Spinner { stopped, task }
+    }
+
+    /// 停止并清除提示行（可重复调用）
+    pub fn stop(&self) {
+        let mut stopped = self.stopped.lock().unwrap_or_else(PoisonError::into_inner);
+        if !*stopped {
+            *stopped = true;
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop();
+        self.task.abort();
+    }
+}
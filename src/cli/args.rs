@@ -0,0 +1,358 @@
+use anyhow::Result;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// serve 命令的默认端口
+pub const DEFAULT_SERVE_PORT: u16 = 8080;
+
+/// 帮助信息模板（替换 clap 默认的英文标题）
+const HELP_TEMPLATE: &str = "\
+{before-help}{about-with-newline}
+用法：{usage}
+
+{all-args}{after-help}";
+
+/// 顶层帮助附带的交互模式命令说明
+const INTERACTIVE_HELP: &str = "\
+交互模式命令:
+  /clear        - 清空当前会话历史
+  /undo         - 撤销最后一轮对话
+  /new [--id <ID>] [名称] - 新建会话（可指定 ID）
+  /fork         - 复制当前会话为分支并切换过去
+  /resume [ID]  - 切换会话（不带参数显示列表）
+  /save         - 立即保存当前会话
+  /export <文件> - 导出当前会话为 Markdown
+  /dryrun [消息] - 预览下一轮将发送的请求（不发送）
+  /toolplan <消息> - 列出模型会调用的工具及理由（不执行）
+  /tools        - 列出可用工具及参数
+  /img <路径>   - 附加图片到下一条消息（需视觉模型）
+  /model <名称> - 切换当前会话使用的模型
+  /compact      - 将较早的历史总结为摘要，缩短上下文
+  /reload-prompt - 重新读取提示文件和长期记忆，更新系统提示
+  /json         - 切换 JSON 模式
+  /plan on|off  - 计划模式（写操作只展示不执行）
+  /quit         - 退出
+  /help         - 显示帮助";
+
+/// 🤖 rox - 本地 AI 助手
+#[derive(Parser)]
+#[command(
+    name = "rox",
+    disable_help_flag = true,
+    disable_help_subcommand = true,
+    subcommand_value_name = "命令",
+    subcommand_help_heading = "命令",
+    after_help = INTERACTIVE_HELP
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalOptions,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// 解析命令行参数；帮助和参数错误由 clap 输出后退出
+    pub fn parse_args() -> Self {
+        let matches = localize(Cli::command()).get_matches();
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    /// 打印顶层帮助信息
+    pub fn print_help() {
+        let mut command = localize(Cli::command());
+        if crate::ui::emoji_enabled() {
+            let _ = command.print_help();
+        } else {
+            ui_print!("{}", command.render_help());
+        }
+    }
+
+    /// 按命令定义生成 shell 补全脚本并输出到 stdout（包含全局选项和别名）
+    pub fn print_completions(shell: Shell) {
+        let mut command = localize(Cli::command());
+        clap_complete::generate(shell, &mut command, "rox", &mut std::io::stdout());
+    }
+}
+
+/// 为命令及其子命令设置中文帮助模板和分组标题
+fn localize(command: clap::Command) -> clap::Command {
+    // 自定义分组中的位置参数默认按名称排序，这里按定义顺序显示
+    let mut position = 0;
+    command
+        .help_template(HELP_TEMPLATE)
+        .mut_args(|arg| match arg.get_help_heading() {
+            Some(_) => arg,
+            None if arg.is_positional() => {
+                position += 1;
+                arg.help_heading("参数").display_order(position)
+            }
+            None => arg.help_heading("选项"),
+        })
+        .arg(
+            clap::Arg::new("help")
+                .short('h')
+                .long("help")
+                .action(ArgAction::Help)
+                .help("显示帮助信息")
+                .help_heading(GLOBAL_HEADING)
+                .display_order(usize::MAX),
+        )
+        .disable_help_flag(true)
+        .mut_subcommands(localize)
+}
+
+/// 全局选项在帮助中的分组标题
+const GLOBAL_HEADING: &str = "全局选项";
+
+/// 全局选项：可放在命令前后任意位置
+#[derive(Args, Default)]
+#[command(next_help_heading = GLOBAL_HEADING)]
+pub struct GlobalOptions {
+    /// 配置文件路径（默认 ~/.rox/config.toml）
+    #[arg(long, global = true, value_name = "文件")]
+    pub config: Option<PathBuf>,
+
+    /// 覆盖配置中的模型（优先于会话保存的模型）
+    #[arg(long, global = true, value_name = "模型")]
+    pub model: Option<String>,
+
+    /// 覆盖配置中的 workspace 目录
+    #[arg(long, global = true, value_name = "目录", value_parser = absolute_path)]
+    pub workspace: Option<PathBuf>,
+
+    /// 覆盖配置中的会话目录
+    #[arg(long, global = true, value_name = "目录", value_parser = absolute_path)]
+    pub sessions_dir: Option<PathBuf>,
+
+    /// 详细日志模式（显示工具调用详情）
+    #[arg(short, long, visible_alias = "log", global = true)]
+    pub verbose: bool,
+
+    /// 安静模式（只输出错误日志）
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// 纯文本输出（emoji 替换为 [OK] 等文字标记或去掉）
+    #[arg(long, global = true)]
+    pub no_emoji: bool,
+}
+
+/// 转为绝对路径，避免工具的路径校验依赖当前目录
+fn absolute_path(value: &str) -> Result<PathBuf, String> {
+    std::path::absolute(value).map_err(|e| format!("路径无效：{}", e))
+}
+
+impl GlobalOptions {
+    /// 配置文件路径（--config 或默认位置）
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(Config::default_path)
+    }
+
+    /// 是否通过命令行覆盖了配置
+    pub fn has_overrides(&self) -> bool {
+        self.model.is_some() || self.sessions_dir.is_some() || self.workspace.is_some()
+    }
+
+    /// 用命令行指定的值覆盖配置
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(model) = &self.model {
+            config.agent.model = model.clone();
+        }
+        if let Some(dir) = &self.sessions_dir {
+            config.session.storage_path = dir.clone();
+        }
+        if let Some(dir) = &self.workspace {
+            config.workspace.root = dir.clone();
+        }
+    }
+
+    /// 加载配置文件并应用覆盖；配置中关闭 emoji 时同时切换输出主题
+    pub fn load_config(&self) -> Result<Config> {
+        let mut config = Config::load(&self.config_path())?;
+        self.apply(&mut config);
+        if !config.agent.emoji {
+            crate::ui::set_emoji(false);
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// 进入交互模式
+    #[command(visible_alias = "a")]
+    Agent(AgentArgs),
+
+    /// 单次问答，只输出最终回复（未给出问题时读取管道输入）
+    #[command(visible_alias = "q")]
+    Ask(AskArgs),
+
+    /// 单次补全（Ollama /api/generate，不带系统提示、历史和工具；未给出提示时读取管道输入）
+    Gen {
+        /// 提示
+        #[arg(value_name = "提示")]
+        prompt: Vec<String>,
+    },
+
+    /// 初始化配置
+    Onboard,
+
+    /// 管理已保存的会话
+    #[command(subcommand, subcommand_value_name = "子命令", subcommand_help_heading = "子命令")]
+    Session(SessionCommand),
+
+    /// 用当前模型在新会话中重放会话的用户输入
+    Replay {
+        /// 会话 ID（支持前缀）
+        id: String,
+    },
+
+    /// 启动 HTTP 服务（POST /chat、GET /sessions，仅监听本机）
+    Serve {
+        /// 监听端口
+        #[arg(short, long, value_name = "端口", default_value_t = DEFAULT_SERVE_PORT)]
+        port: u16,
+    },
+
+    /// 生成 shell 补全脚本
+    Completions {
+        /// bash / zsh / fish / powershell / elvish
+        shell: Shell,
+    },
+
+    /// 显示帮助信息
+    #[command(visible_alias = "h")]
+    Help,
+}
+
+/// agent 与 ask 共用的模式选项
+#[derive(Args)]
+pub struct ModeArgs {
+    /// JSON 模式（强制模型输出合法 JSON，禁用工具）
+    #[arg(long)]
+    pub json_mode: bool,
+
+    /// 计划模式（写操作只展示不执行，只读工具照常执行）
+    #[arg(long)]
+    pub plan: bool,
+}
+
+#[derive(Args)]
+pub struct AgentArgs {
+    #[command(flatten)]
+    pub mode: ModeArgs,
+
+    /// 确认模式（执行工具前询问，可编辑参数）
+    #[arg(long)]
+    pub confirm: bool,
+
+    /// 启动时列出最近的会话供选择（也可新建会话）
+    #[arg(long)]
+    pub pick: bool,
+}
+
+/// 结构化输出格式
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormatArg {
+    Json,
+}
+
+#[derive(Args)]
+pub struct AskArgs {
+    /// 附加文件内容（可重复）
+    #[arg(short, long = "file", value_name = "文件")]
+    pub files: Vec<PathBuf>,
+
+    /// 附加图片（可重复，需使用支持视觉的模型）
+    #[arg(long = "image", value_name = "图片")]
+    pub images: Vec<PathBuf>,
+
+    /// 要求返回合法 JSON
+    #[arg(long, value_name = "格式")]
+    pub format: Option<OutputFormatArg>,
+
+    /// 按 JSON Schema 约束输出
+    #[arg(long, value_name = "文件")]
+    pub schema: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub mode: ModeArgs,
+
+    /// 问题
+    #[arg(value_name = "问题")]
+    pub question: Vec<String>,
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// 列出已保存的会话
+    List {
+        /// 以 JSON 数组输出 id、name、时间、消息数、token 统计
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// 导出会话为 Markdown
+    Export {
+        /// 会话 ID（支持前缀）
+        id: String,
+        /// 输出文件
+        #[arg(value_name = "文件")]
+        file: PathBuf,
+        /// 导出时包含系统提示
+        #[arg(long)]
+        include_system: bool,
+    },
+
+    /// 导出全部会话（JSON + manifest.json）到目录，目标以 .zip 结尾时打包为 zip
+    ExportAll {
+        /// 目标目录或 .zip 文件
+        #[arg(value_name = "目录或zip")]
+        target: PathBuf,
+        /// 同时导出每个会话的 Markdown
+        #[arg(long)]
+        markdown: bool,
+    },
+
+    /// 复制会话为新的分支会话并设为当前会话（原会话不变）
+    Fork {
+        /// 会话 ID（支持前缀）
+        id: String,
+    },
+
+    /// 从会话 JSON 或导出的 Markdown 导入会话（分配新 ID）
+    Import {
+        #[arg(value_name = "文件")]
+        file: PathBuf,
+    },
+
+    /// 重命名会话
+    Rename {
+        /// 会话 ID（支持前缀）
+        id: String,
+        /// 新名称
+        #[arg(value_name = "名称", required = true)]
+        name: Vec<String>,
+    },
+
+    /// 删除会话
+    #[command(visible_alias = "rm")]
+    Delete {
+        /// 会话 ID（支持前缀）
+        id: String,
+    },
+
+    /// 按内容搜索会话（不区分大小写）
+    Search {
+        #[arg(value_name = "关键词", required = true)]
+        query: Vec<String>,
+    },
+
+    /// 将无法解析的会话文件重命名为 .corrupt 备份，使其不再出现在加载警告中
+    Repair,
+}
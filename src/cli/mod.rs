@@ -0,0 +1,1204 @@
+mod args;
+mod prompt;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+
+use reedline::{Reedline, Signal, DefaultHinter, DefaultCompleter};
+use similar::{ChangeTag, TextDiff};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
+
+use crate::agent::{Agent, Approval, AuditLog, LlmClient, SessionManager};
+use crate::config::{Backend, Config, WorkspaceConfig};
+use crate::http;
+use crate::server;
+use crate::tools::registry::{get_tools_static, validate_tool_names};
+use crate::tools::ToolExecutor;
+use crate::ui;
+
+use args::{AgentArgs, AskArgs, Cli, Command, GlobalOptions, SessionCommand};
+use prompt::SessionPrompt;
+
+/// Onboard 命令 - 初始化配置和 workspace
+fn run_onboard(global: &GlobalOptions) -> Result<()> {
+    ui_println!("🚀 初始化 rox 配置...\n");
+
+    // 命令行覆盖的路径只用于本次创建目录和模板，不写入配置文件
+    let saved = Config::default();
+    let mut config = saved.clone();
+    global.apply(&mut config);
+
+    config.ensure_workspace()
+        .context("创建 workspace 目录失败")?;
+    config.ensure_sessions()
+        .context("创建 sessions 目录失败")?;
+
+    ui_println!("✅ 创建目录:");
+    ui_println!("   Workspace: {}", config.workspace.root.display());
+    ui_println!("   Sessions:  {}", config.session.storage_path.display());
+    ui_println!();
+
+    let config_path = global.config_path();
+
+    saved.save(&config_path)
+        .context("保存配置文件失败")?;
+    ui_println!("✅ 保存配置：{}", config_path.display());
+    if global.has_overrides() {
+        ui_println!("   （--model / --workspace / --sessions-dir 仅对本次运行生效，未写入配置文件）");
+    }
+    ui_println!();
+
+    let templates = [
+        ("AGENT.md", "# 角色定义\n\n你是一个智能助手，旨在帮助用户完成各种任务。\n你具备使用工具的能力，可以协助用户处理文件、获取信息等。\n"),
+        ("SOUL.md", "# 对话风格\n\n- 简洁明了\n- 友好专业\n- 用中文回复\n"),
+        ("USER.md", "# 用户信息\n\n在此记录你的个人偏好、背景信息和特殊需求。\n\n例如：\n- 偏好的沟通方式\n- 专业领域背景\n- 特定任务需求\n"),
+    ];
+    for (file, content) in templates {
+        let path = config.workspace.root.join(file);
+        std::fs::write(&path, content)?;
+        ui_println!("✅ 创建：{}", path.display());
+    }
+    ui_println!();
+
+    ui_println!("🎉 初始化完成！");
+    ui_println!();
+    ui_println!("你可以:");
+    ui_println!("  1. 编辑 {}/*.md 文件自定义你的助手", config.workspace.root.display());
+    ui_println!("  2. 运行 'rox agent' 开始对话");
+
+    Ok(())
+}
+
+/// 截断字符串（正确处理 UTF-8）
+fn truncate_str(s: &str, max_len: usize) -> String {
+    if s.len() <= max_len {
+        return s.to_string();
+    }
+
+    let mut result = String::new();
+    for (i, c) in s.char_indices() {
+        if i + c.len_utf8() > max_len - 3 {
+            result.push_str("...");
+            break;
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// 打印历史消息（限制条数）
+fn print_session_history(session_manager: &SessionManager, max_display: usize) {
+    if let Some(session) = session_manager.current() {
+        let messages = session.context().raw_messages();
+        if messages.is_empty() {
+            return;
+        }
+
+        let display_count = messages.len().min(max_display);
+        let skip_count = messages.len().saturating_sub(display_count);
+
+        if skip_count > 0 {
+            ui_println!("📜 历史消息：{} 条（显示最近 {} 条）", messages.len(), display_count);
+        } else {
+            ui_println!("📜 历史消息：{} 条", messages.len());
+        }
+        ui_println!();
+
+        for msg in messages.iter().skip(skip_count) {
+            let preview = truncate_str(&msg.content, 100);
+            match msg.role.as_str() {
+                "user" => ui_println!("👤 你：{}", preview),
+                "assistant" => ui_println!("🤖 AI: {}", preview),
+                "tool" => ui_println!("🔧 工具：{}", preview),
+                _ => {}
+            }
+        }
+        ui_println!();
+    }
+}
+
+/// 打印交互帮助
+fn print_interactive_help() {
+    ui_println!("可用命令:");
+    ui_println!("  /clear        - 清空当前会话历史");
+    ui_println!("  /undo         - 撤销最后一轮对话");
+    ui_println!("  /new [--id <ID>] [名称] - 新建会话（可指定 ID）");
+    ui_println!("  /fork         - 复制当前会话为分支并切换过去");
+    ui_println!("  /resume [ID]  - 切换会话（不带参数显示列表）");
+    ui_println!("  /save         - 立即保存当前会话");
+    ui_println!("  /export <文件> - 导出当前会话为 Markdown");
+    ui_println!("  /dryrun [消息] - 预览下一轮将发送的请求（不发送）");
+    ui_println!("  /toolplan <消息> - 列出模型会调用的工具及理由（不执行）");
+    ui_println!("  /tools        - 列出可用工具及参数");
+    ui_println!("  /img <路径>   - 附加图片到下一条消息（需视觉模型）");
+    ui_println!("  /model <名称> - 切换当前会话使用的模型");
+    ui_println!("  /compact      - 将较早的历史总结为摘要，缩短上下文");
+    ui_println!("  /reload-prompt - 重新读取提示文件和长期记忆，更新系统提示");
+    ui_println!("  /json         - 切换 JSON 模式");
+    ui_println!("  /plan on|off  - 计划模式（写操作只展示不执行）");
+    ui_println!("  /quit         - 退出");
+    ui_println!("  /help         - 显示此帮助");
+    ui_println!();
+    ui_println!("回复生成过程中按 Ctrl-C 可取消本轮对话");
+    ui_println!();
+}
+
+/// 处理斜杠命令，返回是否退出
+async fn handle_command(
+    agent: &mut Agent,
+    session_manager: &mut SessionManager,
+    workspace: &WorkspaceConfig,
+    cmd: &str,
+) -> bool {
+    let parts: Vec<&str> = cmd.split_whitespace().collect();
+    let command = parts.first().map(|s| s.to_lowercase()).unwrap_or_default();
+
+    match command.as_str() {
+        "/quit" | "/exit" => {
+            ui_println!("👋 再见！");
+            true
+        }
+        "/clear" => {
+            if let Some(session) = session_manager.current_mut() {
+                session.context_mut().clear();
+            }
+            session_manager.auto_save();
+            ui_println!("✅ 已清空当前会话历史\n");
+            false
+        }
+        "/undo" => {
+            let removed = session_manager
+                .current_mut()
+                .map(|session| session.context_mut().undo_last_turn())
+                .unwrap_or(0);
+            if removed == 0 {
+                ui_println!("📭 没有可撤销的对话\n");
+            } else {
+                session_manager.auto_save();
+                ui_println!("↩️ 已撤销最后一轮对话（移除 {} 条消息）\n", removed);
+            }
+            false
+        }
+        "/new" => {
+            let (id, name) = match parts.get(1) {
+                Some(&"--id") => match parts.get(2) {
+                    Some(id) => (Some(*id), parts[3..].join(" ")),
+                    None => {
+                        ui_println!("用法：/new [--id <ID>] [名称]\n");
+                        return false;
+                    }
+                },
+                _ => (None, parts[1..].join(" ")),
+            };
+            let name = (!name.is_empty()).then_some(name);
+            let Some(config) = session_manager.current().map(|s| s.config().clone()) else {
+                ui_println!("❌ 没有当前会话\n");
+                return false;
+            };
+
+            let result = match id {
+                Some(id) => session_manager.create_with_id(id, name, config).map(|s| s.id().to_string()),
+                None => Ok(session_manager.create(name, config).id().to_string()),
+            };
+            match result {
+                Ok(id) => {
+                    session_manager.auto_save();
+                    ui_println!("✅ 已新建会话：{}\n", id);
+                }
+                Err(e) => ui_println!("❌ {}\n", e),
+            }
+            false
+        }
+        "/fork" => {
+            let Some(id) = session_manager.current_session_id().map(str::to_string) else {
+                ui_println!("❌ 没有当前会话\n");
+                return false;
+            };
+            match session_manager.fork(&id) {
+                Ok(fork_id) => {
+                    session_manager.auto_save();
+                    ui_println!("✅ 已创建分支会话：{}（来自 {}）\n", fork_id, id);
+                }
+                Err(e) => ui_println!("❌ {}\n", e),
+            }
+            false
+        }
+        "/resume" => {
+            if let Some(prefix) = parts.get(1) {
+                match session_manager.resolve_id(prefix) {
+                    Ok(id) => {
+                        session_manager.switch(&id);
+                        ui_println!("✅ 已切换到会话：{}\n", id);
+                        sync_session_config(agent, session_manager);
+                    }
+                    Err(e) => ui_println!("❌ {}\n", e),
+                }
+            } else {
+                print_session_list(session_manager);
+            }
+            false
+        }
+        "/save" => {
+            match agent.save_current_session(session_manager) {
+                Ok(path) => ui_println!("✅ 已保存到：{}\n", path.display()),
+                Err(e) => ui_println!("❌ 保存失败：{}\n", e),
+            }
+            false
+        }
+        "/export" => {
+            match parts.get(1) {
+                Some(file) => match session_manager.current() {
+                    Some(session) => match std::fs::write(file, session.export_markdown(false)) {
+                        Ok(()) => ui_println!("✅ 已导出到：{}\n", file),
+                        Err(e) => ui_println!("❌ 导出失败：{}\n", e),
+                    },
+                    None => ui_println!("❌ 没有当前会话\n"),
+                },
+                None => ui_println!("用法：/export <文件.md>\n"),
+            }
+            false
+        }
+        "/dryrun" => {
+            let input = cmd.split_once(char::is_whitespace).map(|(_, rest)| rest.trim());
+            match agent.preview_request(session_manager, input.filter(|s| !s.is_empty())) {
+                Ok((url, body)) => {
+                    ui_println!("📤 POST {}", url);
+                    ui_println!("{}\n", body);
+                }
+                Err(e) => ui_println!("❌ {}\n", e),
+            }
+            false
+        }
+        "/toolplan" => {
+            match cmd.split_once(char::is_whitespace).map(|(_, rest)| rest.trim()) {
+                Some(input) if !input.is_empty() => {
+                    match agent.tool_plan(session_manager, input).await {
+                        Ok(plan) => {
+                            if !agent.is_streaming() {
+                                ui_println!("🤖 AI: {}\n", plan);
+                            }
+                        }
+                        Err(e) => ui_println!("❌ 错误：{:#}\n", e),
+                    }
+                }
+                _ => ui_println!("用法：/toolplan <消息>\n"),
+            }
+            false
+        }
+        "/img" => {
+            match cmd.split_once(char::is_whitespace).map(|(_, rest)| rest.trim()) {
+                Some(path) if !path.is_empty() => match agent.attach_image(Path::new(path)) {
+                    Ok(count) => ui_println!("🖼️ 已附加图片：{}（共 {} 张，随下一条消息发送）\n", path, count),
+                    Err(e) => ui_println!("❌ {}\n", e),
+                },
+                _ => ui_println!("用法：/img <图片路径>\n"),
+            }
+            false
+        }
+        "/model" => {
+            match parts.get(1) {
+                Some(model) => {
+                    let old = agent.set_model(model);
+                    if let Some(session) = session_manager.current_mut() {
+                        session.set_model(model);
+                    }
+                    session_manager.auto_save();
+                    ui_println!("✅ 已切换模型：{} -> {}\n", old, model);
+                }
+                None => {
+                    ui_println!("当前模型：{}", agent.model());
+                    ui_println!("用法：/model <模型名>（模型名不能为空）\n");
+                }
+            }
+            false
+        }
+        "/compact" => {
+            ui_println!("🗜️ 正在压缩历史...");
+            match agent.compact_history(session_manager).await {
+                Ok(0) => ui_println!("ℹ️ 历史较短，无需压缩\n"),
+                Ok(removed) => ui_println!("✅ 已将 {} 条较早的消息压缩为摘要\n", removed),
+                Err(e) => ui_println!("❌ 压缩失败：{}\n", e),
+            }
+            false
+        }
+        "/reload-prompt" => {
+            let Some(session) = session_manager.current_mut() else {
+                ui_println!("❌ 没有当前会话\n");
+                return false;
+            };
+            let old = session.context().system_prompt().to_string();
+            match session.context_mut().load_system_prompt(workspace) {
+                Ok(new) if new == old => ui_println!("ℹ️ 系统提示没有变化\n"),
+                Ok(new) => {
+                    session_manager.auto_save();
+                    ui_println!("✅ 已重新加载系统提示：{}\n", describe_prompt_change(&old, &new));
+                }
+                Err(e) => ui_println!("❌ 重新加载系统提示失败：{}\n", e),
+            }
+            false
+        }
+        "/json" => {
+            let enabled = !agent.json_mode();
+            agent.set_json_mode(enabled);
+            if enabled {
+                ui_println!("✅ 已开启 JSON 模式（工具已禁用）\n");
+            } else {
+                ui_println!("✅ 已关闭 JSON 模式\n");
+            }
+            false
+        }
+        "/plan" => {
+            match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                Some("on") => {
+                    agent.set_plan_mode(true);
+                    ui_println!("✅ 已开启计划模式（写操作只展示不执行）\n");
+                }
+                Some("off") => {
+                    agent.set_plan_mode(false);
+                    ui_println!("✅ 已关闭计划模式\n");
+                }
+                _ => {
+                    let state = if agent.plan_mode() { "开启" } else { "关闭" };
+                    ui_println!("计划模式：{}（用法：/plan on|off）\n", state);
+                }
+            }
+            false
+        }
+        "/tools" => {
+            print_tools(agent);
+            false
+        }
+        "/help" | "/h" => {
+            print_interactive_help();
+            false
+        }
+        _ => {
+            ui_println!("❌ 未知命令：{}", cmd);
+            ui_println!("输入 /help 查看帮助\n");
+            false
+        }
+    }
+}
+
+/// 描述系统提示的变化：增删的行数，以及新增、更新、移除的分节（提示文件和长期记忆各为一节）
+fn describe_prompt_change(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let (mut added, mut removed) = (0, 0);
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let old_sections = prompt_sections(old);
+    let new_sections = prompt_sections(new);
+    let mut changes = Vec::new();
+    for (heading, body) in &new_sections {
+        match old_sections.iter().find(|(h, _)| h == heading) {
+            None => changes.push(format!("新增「{}」", heading)),
+            Some((_, old_body)) if old_body != body => changes.push(format!("更新「{}」", heading)),
+            Some(_) => {}
+        }
+    }
+    for (heading, _) in &old_sections {
+        if !new_sections.iter().any(|(h, _)| h == heading) {
+            changes.push(format!("移除「{}」", heading));
+        }
+    }
+
+    let mut summary = format!("+{} / -{} 行", added, removed);
+    if !changes.is_empty() {
+        summary.push_str(&format!("（{}）", changes.join("、")));
+    }
+    summary
+}
+
+/// 按 `## 标题` 拆分系统提示，返回 (标题, 内容)
+fn prompt_sections(prompt: &str) -> Vec<(&str, String)> {
+    let mut sections: Vec<(&str, String)> = Vec::new();
+    for line in prompt.lines() {
+        if let Some(heading) = line.strip_prefix("## ") {
+            sections.push((heading.trim(), String::new()));
+        } else if let Some((_, body)) = sections.last_mut() {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    sections
+}
+
+/// 打印已启用的工具（名称、说明、参数）和未启用的内置工具
+fn print_tools(agent: &Agent) {
+    let tools = agent.tools();
+    ui_println!("🧰 可用工具（{} 个）：", tools.len());
+    if agent.json_mode() {
+        ui_println!("   （JSON 模式下不向模型提供工具）");
+    }
+    ui_println!();
+
+    for tool in tools {
+        let function = &tool.function;
+        ui_println!("  {} - {}", function.name, function.description);
+
+        let required: Vec<&str> = function.parameters["required"]
+            .as_array()
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        if let Some(properties) = function.parameters["properties"].as_object() {
+            // 必填参数按 required 的顺序在前，可选参数在后
+            let mut names: Vec<&String> = properties.keys().collect();
+            names.sort_by_key(|name| required.iter().position(|r| r == name).unwrap_or(usize::MAX));
+            for name in names {
+                let optional = if required.contains(&name.as_str()) { "" } else { "（可选）" };
+                let description = properties[name]["description"].as_str().unwrap_or("");
+                ui_println!("      {}{}: {}", name, optional, description);
+            }
+        }
+    }
+
+    let disabled: Vec<&str> = get_tools_static()
+        .iter()
+        .map(|t| t.function.name.as_str())
+        .filter(|name| !tools.iter().any(|t| t.function.name == *name))
+        .collect();
+    if !disabled.is_empty() {
+        ui_println!();
+        ui_println!("⛔ 未启用：{}", disabled.join("、"));
+    }
+    ui_println!();
+}
+
+/// 打印会话列表
+fn print_session_list(session_manager: &SessionManager) {
+    let sessions = session_manager.list();
+
+    if sessions.is_empty() {
+        ui_println!("📭 暂无会话\n");
+        return;
+    }
+
+    ui_println!("📋 会话列表:");
+    ui_println!();
+
+    let current_id = session_manager.current_session_id();
+
+    for (id, metadata) in sessions {
+        let name_str = metadata.name.as_deref().unwrap_or("(未命名)");
+        let msgs = metadata.message_count;
+        let short_id = if id.len() > 8 { &id[..8] } else { id };
+        let marker = if current_id == Some(id) { "👉" } else { "  " };
+        let tokens = if metadata.total_prompt_tokens + metadata.total_completion_tokens > 0 {
+            format!(
+                "，输入 {} / 输出 {} tokens",
+                metadata.total_prompt_tokens, metadata.total_completion_tokens
+            )
+        } else {
+            String::new()
+        };
+        ui_println!("{} {} - {} ({}条消息{})", marker, short_id, name_str, msgs, tokens);
+    }
+    ui_println!();
+}
+
+/// 确认模式：执行工具前询问用户（执行 / 拒绝 / 编辑参数）
+fn confirm_tool_call(name: &str, args: &HashMap<String, Value>) -> Approval {
+    let pretty = serde_json::to_string_pretty(args).unwrap_or_default();
+    ui_println!("❓ 即将调用工具：{}", name);
+    ui_println!("{}", pretty);
+
+    loop {
+        ui_print!("执行？[y]是 / [n]否 / [e]编辑参数：");
+        let _ = std::io::stdout().flush();
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).is_err() {
+            return Approval::Reject;
+        }
+
+        match answer.trim().to_lowercase().as_str() {
+            "" | "y" | "yes" => return Approval::Approve,
+            "n" | "no" => return Approval::Reject,
+            "e" | "edit" => match edit_tool_args(&pretty) {
+                Ok(edited) => {
+                    ui_println!("✅ 使用编辑后的参数：{}", serde_json::to_string(&edited).unwrap_or_default());
+                    return Approval::Edit(edited);
+                }
+                Err(e) => ui_println!("❌ 编辑参数失败：{}", e),
+            },
+            _ => ui_println!("请输入 y、n 或 e"),
+        }
+    }
+}
+
+/// 编辑工具参数：设置了 $EDITOR 时用编辑器打开，否则在命令行输入一行 JSON
+fn edit_tool_args(current: &str) -> Result<HashMap<String, Value>> {
+    let edited = match std::env::var("EDITOR").ok().filter(|e| !e.trim().is_empty()) {
+        Some(editor) => {
+            let path = std::env::temp_dir().join(format!("rox-args-{}.json", uuid::Uuid::new_v4()));
+            std::fs::write(&path, current).context("创建临时文件失败")?;
+
+            let mut parts = editor.split_whitespace();
+            let program = parts.next().unwrap_or("vi");
+            let status = std::process::Command::new(program)
+                .args(parts)
+                .arg(&path)
+                .status()
+                .with_context(|| format!("启动编辑器失败：{}", editor));
+
+            let content = std::fs::read_to_string(&path);
+            let _ = std::fs::remove_file(&path);
+
+            if !status?.success() {
+                return Err(anyhow::anyhow!("编辑器异常退出"));
+            }
+            content.context("读取编辑结果失败")?
+        }
+        None => {
+            ui_print!("输入新的参数（JSON）：");
+            let _ = std::io::stdout().flush();
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).context("读取输入失败")?;
+            line
+        }
+    };
+
+    serde_json::from_str(edited.trim()).context("参数不是合法的 JSON 对象")
+}
+
+/// 按配置构建 Agent
+fn build_agent(config: &Config) -> Result<Agent> {
+    let client = http::build_client(&config.http)?;
+    let llm_client = LlmClient::new(config.agent.clone(), client.clone());
+    let tool_executor = ToolExecutor::new(
+        config.workspace.root.clone(),
+        config.workspace.read_only,
+        &config.tools,
+        &client,
+    );
+    validate_tool_names(tool_executor.get_tools())?;
+
+    let mut agent = Agent::new(llm_client, tool_executor, config.agent.clone());
+    if config.session.audit_log {
+        agent.set_audit_log(Some(AuditLog::open(&config.session.storage_path)?));
+    }
+    Ok(agent)
+}
+
+/// 设置 Agent 和 SessionManager
+fn setup_agent(config: &Config, global: &GlobalOptions, pick: bool) -> Result<(Agent, SessionManager)> {
+    let mut session_manager = load_sessions(config)?;
+    if pick {
+        pick_session(&mut session_manager, config);
+    }
+    if session_manager.current().is_none() {
+        session_manager.create(None, config.agent.clone());
+    }
+
+    let mut agent = build_agent(config)?;
+    // 命令行指定的模型（已写入 config）优先于会话保存的模型
+    if let Some(model) = &global.model {
+        if let Some(session) = session_manager.current_mut() {
+            session.set_model(model);
+        }
+    }
+    sync_session_config(&mut agent, &session_manager);
+
+    Ok((agent, session_manager))
+}
+
+/// 会话选择菜单列出的最近会话数
+const PICKER_MAX_SESSIONS: usize = 10;
+
+/// 启动时列出最近更新的会话供选择，也可新建会话（没有会话或非交互环境时保持默认行为）
+fn pick_session(session_manager: &mut SessionManager, config: &Config) {
+    let mut sessions = session_manager.list();
+    if sessions.is_empty() {
+        return;
+    }
+    if !std::io::stdin().is_terminal() {
+        ui_println!("⚠️ 非交互环境，跳过会话选择\n");
+        return;
+    }
+
+    sessions.sort_by_key(|(_, metadata)| std::cmp::Reverse(metadata.updated_at));
+    let current_id = session_manager.current_session_id();
+    let ids: Vec<String> = sessions.iter().take(PICKER_MAX_SESSIONS).map(|(id, _)| id.to_string()).collect();
+
+    ui_println!("📋 最近的会话：");
+    ui_println!("  0. ➕ 新建会话");
+    for (i, (id, metadata)) in sessions.iter().take(PICKER_MAX_SESSIONS).enumerate() {
+        let short_id = if id.len() > 8 { &id[..8] } else { id };
+        let marker = if current_id == Some(*id) { " 👈 当前" } else { "" };
+        ui_println!(
+            "  {}. {} - {} ({}条消息，{}){}",
+            i + 1,
+            short_id,
+            metadata.name.as_deref().unwrap_or("(未命名)"),
+            metadata.message_count,
+            metadata.updated_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            marker
+        );
+    }
+
+    let Some(choice) = prompt_line(&format!("请选择会话 [0-{}]（回车保持当前会话）：", ids.len())) else {
+        return;
+    };
+    match choice.parse::<usize>() {
+        Ok(0) => {
+            let id = session_manager.create(None, config.agent.clone()).id().to_string();
+            ui_println!("✅ 已新建会话：{}\n", id);
+        }
+        Ok(n) if n <= ids.len() => {
+            session_manager.switch(&ids[n - 1]);
+            ui_println!("✅ 已切换到会话：{}\n", ids[n - 1]);
+        }
+        _ if choice.is_empty() => ui_println!(),
+        _ => ui_println!("❌ 无效的选择：{}，保持当前会话\n", choice),
+    }
+}
+
+/// 启动时检查后端和模型：服务无法连接时只给出警告；模型未配置或未安装时列出已安装模型供选择（非交互环境保持原配置）
+async fn pick_model_if_needed(agent: &mut Agent, session_manager: &mut SessionManager, config_path: &Path) {
+    let current = agent.model().to_string();
+    let models = match agent.health_check().await {
+        Ok(models) => models,
+        Err(e) => {
+            ui_eprintln!("⚠️ {}", e);
+            ui_eprintln!("   {}\n", e.root_cause());
+            return;
+        }
+    };
+
+    let installed = models.iter().any(|m| m == &current || m.strip_suffix(":latest") == Some(current.as_str()));
+    if models.is_empty() || (!current.trim().is_empty() && installed) {
+        return;
+    }
+
+    if current.trim().is_empty() {
+        ui_eprintln!("⚠️ 未配置模型");
+    } else {
+        ui_eprintln!("⚠️ 模型 {} 未安装", current);
+        if agent.backend() == Backend::Ollama {
+            ui_eprintln!("   可运行 ollama pull {} 安装", current);
+        }
+    }
+
+    if !std::io::stdin().is_terminal() {
+        ui_eprintln!("   非交互环境，继续使用配置的模型\n");
+        return;
+    }
+
+    ui_println!("已安装的模型：");
+    for (i, model) in models.iter().enumerate() {
+        ui_println!("  {}. {}", i + 1, model);
+    }
+
+    let Some(choice) = prompt_line(&format!("请选择模型 [1-{}]（回车保持不变）：", models.len())) else {
+        return;
+    };
+    let Some(model) = choice.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(|i| models.get(i)) else {
+        if !choice.is_empty() {
+            ui_println!("❌ 无效的选择：{}，继续使用配置的模型\n", choice);
+        }
+        return;
+    };
+
+    agent.set_model(model);
+    if let Some(session) = session_manager.current_mut() {
+        session.set_model(model);
+    }
+    session_manager.auto_save();
+    ui_println!("✅ 已选择模型：{}", model);
+
+    let save = prompt_line("是否保存到配置文件？[y/N]：").unwrap_or_default();
+    if save.eq_ignore_ascii_case("y") {
+        match Config::save_model(config_path, model) {
+            Ok(()) => ui_println!("✅ 已保存到：{}", config_path.display()),
+            Err(e) => ui_println!("❌ 保存配置失败：{}", e),
+        }
+    }
+    ui_println!();
+}
+
+/// 读取一行输入（去除首尾空白），读取失败时返回 None
+fn prompt_line(prompt: &str) -> Option<String> {
+    ui_print!("{}", prompt);
+    let _ = std::io::stdout().flush();
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+/// 使用当前会话保存的模型和采样参数（模型通过 /model 切换后随会话持久化）
+fn sync_session_config(agent: &mut Agent, session_manager: &SessionManager) {
+    if let Some(session) = session_manager.current() {
+        let old_model = agent.model().to_string();
+        if agent.apply_session_config(session.config()) {
+            if agent.model() != old_model {
+                ui_println!("🔁 使用会话模型：{}", agent.model());
+            } else {
+                ui_println!("🔁 使用会话保存的模型参数");
+            }
+        }
+    }
+}
+
+/// 显示会话状态
+fn display_session_status(session_manager: &SessionManager) {
+    if let Some(session_id) = session_manager.current_session_id() {
+        let short_id = if session_id.len() > 8 { &session_id[..8] } else { session_id };
+        if let Some(session) = session_manager.current() {
+            let msg_count = session.context().len();
+            ui_println!("📝 会话：{} ({}条消息)", short_id, msg_count);
+        } else {
+            ui_println!("📝 会话：{}", short_id);
+        }
+        ui_println!();
+    }
+}
+
+/// Agent 命令 - 交互式对话
+async fn run_agent(args: AgentArgs, global: &GlobalOptions) -> Result<()> {
+    let config = global.load_config()?;
+    config.ensure_workspace()?;
+    config.ensure_sessions()?;
+
+    // 打印欢迎信息
+    ui_println!("╔════════════════════════════════════════╗");
+    ui_println!("║   🤖 rox - 本地 AI 助手                ║");
+    ui_println!("║   模型：{:<24} ║", truncate_str(&config.agent.model, 24));
+    if global.verbose {
+        ui_println!("║   模式：详细日志                      ║");
+    }
+    if args.mode.json_mode {
+        ui_println!("║   模式：JSON                          ║");
+    }
+    if args.confirm {
+        ui_println!("║   模式：工具确认                      ║");
+    }
+    if args.mode.plan {
+        ui_println!("║   模式：计划                          ║");
+    }
+    if config.workspace.read_only {
+        ui_println!("║   模式：只读 workspace                ║");
+    }
+    ui_println!("╚════════════════════════════════════════╝");
+    ui_println!();
+    ui_println!("💡 输入 /help 查看命令，/quit 退出");
+    if !config.session.auto_save {
+        ui_println!("💾 自动保存已关闭，使用 /save 保存会话");
+    }
+    ui_println!();
+
+    // 设置 Agent 和 SessionManager
+    let (mut agent, mut session_manager) = setup_agent(&config, global, args.pick || config.session.pick_on_start)?;
+    pick_model_if_needed(&mut agent, &mut session_manager, &global.config_path()).await;
+    agent.set_json_mode(args.mode.json_mode);
+    agent.set_plan_mode(args.mode.plan);
+    agent.set_spinner(config.agent.spinner && !global.quiet && std::io::stderr().is_terminal());
+    if args.confirm {
+        agent.set_approval(Some(Box::new(confirm_tool_call)));
+    }
+
+    // 显示当前会话信息
+    display_session_status(&session_manager);
+
+    // 显示历史消息（最近 5 条）
+    print_session_history(&session_manager, 5);
+
+    // 设置 readline
+    let completer = DefaultCompleter::default();
+    let hinter = DefaultHinter::default();
+
+    let mut line_editor = Reedline::create()
+        .with_hinter(Box::new(hinter))
+        .with_completer(Box::new(completer));
+
+    loop {
+        // 每轮重新生成，反映最新的消息数和 token 估算
+        let prompt = SessionPrompt::from_session(&session_manager);
+        let sig = line_editor.read_line(&prompt)?;
+
+        match sig {
+            Signal::Success(buffer) => {
+                let input = buffer.trim();
+
+                if input.is_empty() {
+                    continue;
+                }
+
+                // 斜杠命令
+                if input.starts_with('/') {
+                    if handle_command(&mut agent, &mut session_manager, &config.workspace, input).await {
+                        break;
+                    }
+                    continue;
+                }
+
+                // 普通对话；进行中按 Ctrl-C 取消本轮并回到提示符
+                let cancel = CancellationToken::new();
+                let watcher = tokio::spawn({
+                    let cancel = cancel.clone();
+                    async move {
+                        if tokio::signal::ctrl_c().await.is_ok() {
+                            cancel.cancel();
+                        }
+                    }
+                });
+                let result = agent.chat(&mut session_manager, input, &cancel).await;
+                watcher.abort();
+
+                match result {
+                    Ok(reply) => {
+                        if !agent.is_streaming() {
+                            ui_println!("🤖 AI: {}\n", reply);
+                        }
+                    }
+                    Err(_) if cancel.is_cancelled() => {
+                        ui_println!("\n⏹️ 已取消本轮对话\n");
+                    }
+                    Err(e) => {
+                        ui_println!("❌ 错误：{:#}\n", e);
+                    }
+                }
+            }
+            Signal::CtrlD => {
+                ui_println!("\n👋 再见！");
+                break;
+            }
+            Signal::CtrlC => {
+                ui_println!("\n输入 /quit 退出");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 加载已保存的会话（不创建新会话）
+fn load_sessions(config: &Config) -> Result<SessionManager> {
+    let mut session_manager = SessionManager::new(config.session.storage_path.clone(), config.workspace.clone());
+    session_manager.set_auto_save(config.session.auto_save);
+    session_manager.set_format(config.session.format);
+    match session_manager.load_all() {
+        Ok(summary) => {
+            if let Some(warning) = summary.warning() {
+                ui_eprintln!("⚠️ {}\n", warning);
+            }
+        }
+        Err(e) => ui_eprintln!("⚠️ 加载会话失败：{}\n", e),
+    }
+    Ok(session_manager)
+}
+
+/// Session 命令 - 管理已保存的会话
+fn run_session(command: SessionCommand, global: &GlobalOptions) -> Result<()> {
+    let config = global.load_config()?;
+
+    match command {
+        SessionCommand::List { json } => {
+            let session_manager = load_sessions(&config)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&session_manager.summaries())?);
+            } else {
+                print_session_list(&session_manager);
+            }
+        }
+        SessionCommand::Export { id, file, include_system } => {
+            let session_manager = load_sessions(&config)?;
+            let id = session_manager.resolve_id(&id)?;
+            let session = session_manager.get(&id).context("会话不存在")?;
+
+            std::fs::write(&file, session.export_markdown(include_system))
+                .with_context(|| format!("写入文件失败：{}", file.display()))?;
+            ui_println!("✅ 已导出会话 {} 到：{}", id, file.display());
+        }
+        SessionCommand::ExportAll { target, markdown } => {
+            let session_manager = load_sessions(&config)?;
+            let count = session_manager.export_all(&target, markdown)?;
+            ui_println!("✅ 已导出 {} 个会话到：{}", count, target.display());
+        }
+        SessionCommand::Rename { id, name } => {
+            let name = name.join(" ");
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(anyhow::anyhow!("会话名称不能为空"));
+            }
+
+            let mut session_manager = load_sessions(&config)?;
+            let id = session_manager.resolve_id(&id)?;
+            let session = session_manager.get_mut(&id).context("会话不存在")?;
+            let old_name = session.metadata().name.clone().unwrap_or_else(|| "未命名".to_string());
+            session.rename(name);
+            session_manager.save(&id)?;
+
+            ui_println!("✅ 已重命名会话 {}：{} -> {}", id, old_name, name);
+        }
+        SessionCommand::Delete { id } => {
+            let mut session_manager = load_sessions(&config)?;
+            let id = session_manager.resolve_id(&id)?;
+            let name = session_manager.get(&id)
+                .and_then(|s| s.metadata().name.clone())
+                .unwrap_or_else(|| "未命名".to_string());
+            session_manager.delete(&id)?;
+
+            ui_println!("🗑️ 已删除会话 {}（{}）", id, name);
+        }
+        SessionCommand::Fork { id } => {
+            let mut session_manager = load_sessions(&config)?;
+            let id = session_manager.resolve_id(&id)?;
+            let fork_id = session_manager.fork(&id)?;
+            session_manager.save(&fork_id)?;
+
+            ui_println!("✅ 已从会话 {} 创建分支：{}（已设为当前会话）", id, fork_id);
+        }
+        SessionCommand::Import { file } => {
+            let mut session_manager = load_sessions(&config)?;
+            let id = session_manager.import(&file, config.agent.clone())?;
+            let session = session_manager.get(&id).context("会话不存在")?;
+            ui_println!(
+                "✅ 已导入会话 {}（{} 条消息），新 ID：{}",
+                session.metadata().name.as_deref().unwrap_or("(未命名)"),
+                session.metadata().message_count,
+                id
+            );
+        }
+        SessionCommand::Repair => {
+            let mut session_manager = SessionManager::new(config.session.storage_path.clone(), config.workspace.clone());
+            let moved = session_manager.repair()?;
+            if moved.is_empty() {
+                ui_println!("✅ 没有无法解析的会话文件");
+            }
+            for (path, backup) in &moved {
+                ui_println!("📦 已移走：{} -> {}", path.display(), backup.display());
+            }
+        }
+        SessionCommand::Search { query } => {
+            let query = query.join(" ");
+            if query.trim().is_empty() {
+                return Err(anyhow::anyhow!("搜索关键词不能为空"));
+            }
+
+            let session_manager = load_sessions(&config)?;
+            let results = session_manager.search(&query);
+            if results.is_empty() {
+                ui_println!("🔍 未找到包含「{}」的会话", query.trim());
+                return Ok(());
+            }
+
+            ui_println!("🔍 找到 {} 个会话:", results.len());
+            ui_println!();
+            for (id, metadata, snippet) in results {
+                let short_id = if id.len() > 8 { &id[..8] } else { id };
+                ui_println!("  {} - {}", short_id, metadata.name.as_deref().unwrap_or("(未命名)"));
+                ui_println!("      {}", snippet);
+            }
+            ui_println!();
+        }
+    }
+    Ok(())
+}
+
+/// 初始化日志：诊断信息输出到 stderr，设置 RUST_LOG 时以其为准
+fn init_tracing(verbose: bool, quiet: bool) {
+    let level = if quiet {
+        "error"
+    } else if verbose {
+        "debug"
+    } else {
+        "warn"
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(format!("rox={}", level)));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| ui::LogWriter)
+        .without_time()
+        .with_target(false)
+        .with_level(false)
+        .init();
+}
+
+/// Replay 命令 - 在新会话中用当前配置的模型重放已有会话的用户输入
+async fn run_replay(prefix: &str, global: &GlobalOptions) -> Result<()> {
+    let config = global.load_config()?;
+    config.ensure_workspace()?;
+    config.ensure_sessions()?;
+
+    let mut session_manager = load_sessions(&config)?;
+    let source_id = session_manager.resolve_id(prefix)?;
+    let source = session_manager.get(&source_id).context("会话不存在")?;
+    let turns = source.user_turns();
+    if turns.is_empty() {
+        return Err(anyhow::anyhow!("会话 {} 中没有用户消息", source_id));
+    }
+
+    let short_id = &source_id[..source_id.len().min(8)];
+    let name = format!("重放 {}", source.metadata().name.as_deref().unwrap_or(short_id));
+    let previous_id = session_manager.current_session_id().map(str::to_string);
+    let new_id = session_manager.create(Some(name), config.agent.clone()).id().to_string();
+
+    let mut agent = build_agent(&config)?;
+    ui_println!("🔁 使用模型 {} 重放会话 {}（{} 轮）\n", agent.model(), short_id, turns.len());
+
+    for (i, (input, original)) in turns.iter().enumerate() {
+        ui_println!("[{}/{}] 👤 {}", i + 1, turns.len(), input);
+        match agent.chat(&mut session_manager, input, &CancellationToken::new()).await {
+            Ok(reply) => {
+                if !agent.is_streaming() {
+                    ui_println!("🤖 AI: {}\n", reply);
+                }
+            }
+            Err(e) => ui_println!("❌ 错误：{:#}\n", e),
+        }
+        if let Some(original) = original {
+            ui_println!("📜 原回复：{}\n", original);
+        }
+    }
+
+    session_manager.auto_save();
+    // 重放不改变交互模式下恢复的会话
+    if let Some(id) = previous_id {
+        session_manager.set_current(&id);
+    }
+    ui_println!("✅ 重放完成，新会话：{}", new_id);
+    Ok(())
+}
+
+/// Gen 命令 - 单次补全：直接调用 LlmClient::generate，不创建会话、不加载工具
+async fn run_gen(prompt: &[String], global: &GlobalOptions) -> Result<()> {
+    let mut prompt = prompt.join(" ");
+    // 只在标准输入来自管道或文件时读取，避免在终端中无提示地等待输入
+    if prompt.trim().is_empty() && !std::io::stdin().is_terminal() {
+        std::io::stdin().read_to_string(&mut prompt).context("读取标准输入失败")?;
+    }
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err(anyhow::anyhow!("请提供提示（未给出提示时从管道读取标准输入）"));
+    }
+
+    let config = global.load_config()?;
+    let client = http::build_client(&config.http)?;
+    let llm_client = LlmClient::new(config.agent, client);
+    println!("{}", llm_client.generate(prompt).await?);
+    Ok(())
+}
+
+/// Ask 命令 - 单次问答：在新会话中运行一轮对话，只把最终回复输出到 stdout
+async fn run_ask(args: AskArgs, global: &GlobalOptions) -> Result<()> {
+    let mut question = args.question.join(" ");
+    // 只在标准输入来自管道或文件时读取，避免在终端中无提示地等待输入
+    if question.trim().is_empty() && !std::io::stdin().is_terminal() {
+        std::io::stdin().read_to_string(&mut question).context("读取标准输入失败")?;
+    }
+
+    let mut prompt = String::new();
+    for file in &args.files {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("读取文件失败：{}", file.display()))?;
+        prompt.push_str(&format!("【文件：{}】\n{}\n\n", file.display(), content.trim_end()));
+    }
+    prompt.push_str(question.trim());
+
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err(anyhow::anyhow!("请提供问题（未给出问题时从管道读取标准输入）"));
+    }
+
+    let schema = match &args.schema {
+        Some(file) => {
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("读取 Schema 文件失败：{}", file.display()))?;
+            Some(serde_json::from_str::<serde_json::Value>(&content)
+                .with_context(|| format!("Schema 文件不是合法的 JSON：{}", file.display()))?)
+        }
+        None => None,
+    };
+    let structured = args.format.is_some() || schema.is_some();
+
+    let mut config = global.load_config()?;
+    config.ensure_workspace()?;
+    config.ensure_sessions()?;
+    // 流式输出会带 "🤖 AI:" 前缀，单次问答只输出最终回复（也不打印思考过程）
+    config.agent.stream = false;
+    config.agent.show_reasoning = false;
+
+    let mut session_manager = load_sessions(&config)?;
+    // 单次问答的会话只在内存中使用，不写入会话目录
+    session_manager.set_auto_save(false);
+    let previous_id = session_manager.current_session_id().map(str::to_string);
+    session_manager.create(Some("ask".to_string()), config.agent.clone());
+
+    let mut agent = build_agent(&config)?;
+    agent.set_json_mode(args.mode.json_mode);
+    agent.set_plan_mode(args.mode.plan);
+    for image in &args.images {
+        agent.attach_image(image)?;
+    }
+    let result = if structured {
+        agent.chat_structured(&mut session_manager, prompt, schema.as_ref()).await
+            .and_then(|value| Ok(serde_json::to_string_pretty(&value)?))
+    } else {
+        agent.chat(&mut session_manager, prompt, &CancellationToken::new()).await
+    };
+
+    // 单次问答不改变交互模式下恢复的会话
+    match previous_id {
+        Some(id) => {
+            session_manager.set_current(&id);
+        }
+        None => session_manager.clear_current(),
+    }
+
+    println!("{}", result?);
+    Ok(())
+}
+
+/// Serve 命令 - 以 HTTP API 提供 Agent
+async fn run_serve(port: u16, global: &GlobalOptions) -> Result<()> {
+    let mut config = global.load_config()?;
+    config.ensure_workspace()?;
+    config.ensure_sessions()?;
+    // 回复通过 HTTP 返回，不在终端流式打印
+    config.agent.stream = false;
+    config.agent.show_reasoning = false;
+
+    let session_manager = load_sessions(&config)?;
+    let agent = build_agent(&config)?;
+    server::serve(agent, session_manager, config.agent.clone(), port).await
+}
+
+/// 主入口函数
+pub async fn run_cli() -> Result<()> {
+    let Cli { global, command } = Cli::parse_args();
+    ui::set_emoji(!global.no_emoji);
+    // 单次问答默认只输出错误日志，便于脚本使用
+    let quiet = global.quiet || (matches!(command, Some(Command::Ask(_) | Command::Gen { .. })) && !global.verbose);
+    init_tracing(global.verbose, quiet);
+
+    match command {
+        Some(Command::Agent(args)) => run_agent(args, &global).await,
+        Some(Command::Onboard) => run_onboard(&global),
+        Some(Command::Ask(args)) => run_ask(args, &global).await,
+        Some(Command::Gen { prompt }) => run_gen(&prompt, &global).await,
+        Some(Command::Session(command)) => run_session(command, &global),
+        Some(Command::Replay { id }) => run_replay(&id, &global).await,
+        Some(Command::Serve { port }) => run_serve(port, &global).await,
+        Some(Command::Completions { shell }) => {
+            Cli::print_completions(shell);
+            Ok(())
+        }
+        Some(Command::Help) | None => {
+            Cli::print_help();
+            Ok(())
+        }
+    }
+}
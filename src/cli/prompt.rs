@@ -0,0 +1,54 @@
+use reedline::{Prompt, PromptEditMode, PromptHistorySearch, PromptHistorySearchStatus};
+use std::borrow::Cow;
+
+use crate::agent::SessionManager;
+
+/// 交互提示符：显示当前会话的消息数和估算 token，如 `rox(12 · ~1.3k tokens)> `
+pub struct SessionPrompt {
+    messages: usize,
+    tokens: usize,
+}
+
+impl SessionPrompt {
+    pub fn from_session(session_manager: &SessionManager) -> Self {
+        let (messages, tokens) = session_manager
+            .current()
+            .map(|s| (s.context().len(), s.context().estimate_tokens()))
+            .unwrap_or((0, 0));
+        SessionPrompt { messages, tokens }
+    }
+}
+
+fn format_tokens(tokens: usize) -> String {
+    if tokens < 1000 {
+        tokens.to_string()
+    } else {
+        format!("{:.1}k", tokens as f64 / 1000.0)
+    }
+}
+
+impl Prompt for SessionPrompt {
+    fn render_prompt_left(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("rox({} · ~{} tokens)", self.messages, format_tokens(self.tokens)))
+    }
+
+    fn render_prompt_right(&self) -> Cow<'_, str> {
+        Cow::Borrowed("")
+    }
+
+    fn render_prompt_indicator(&self, _edit_mode: PromptEditMode) -> Cow<'_, str> {
+        Cow::Borrowed("> ")
+    }
+
+    fn render_prompt_multiline_indicator(&self) -> Cow<'_, str> {
+        Cow::Borrowed("::: ")
+    }
+
+    fn render_prompt_history_search_indicator(&self, history_search: PromptHistorySearch) -> Cow<'_, str> {
+        let prefix = match history_search.status {
+            PromptHistorySearchStatus::Passing => "",
+            PromptHistorySearchStatus::Failing => "未找到 ",
+        };
+        Cow::Owned(format!("({}搜索：{}) ", prefix, history_search.term))
+    }
+}
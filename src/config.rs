@@ -1,5 +1,8 @@
 use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -10,48 +13,280 @@ fn default_base_dir() -> PathBuf {
         .join(".rox")
 }
 
+/// 是否启用 XDG 目录（需设置 ROX_USE_XDG=1，仅 Linux 生效）
+fn use_xdg() -> bool {
+    cfg!(target_os = "linux")
+        && std::env::var("ROX_USE_XDG")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+}
+
+/// 读取 XDG 环境变量对应的 rox 子目录，未设置时回退到 ~/.rox
+fn xdg_dir(var: &str) -> PathBuf {
+    if use_xdg() {
+        if let Some(dir) = std::env::var_os(var).filter(|v| !v.is_empty()) {
+            return PathBuf::from(dir).join("rox");
+        }
+    }
+    default_base_dir()
+}
+
+/// 配置目录（XDG_CONFIG_HOME/rox 或 ~/.rox）
+fn config_base_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME")
+}
+
+/// 数据目录（XDG_DATA_HOME/rox 或 ~/.rox），存放 workspace 和 sessions
+fn data_base_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME")
+}
+
+/// LLM 后端类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    /// Ollama 原生接口（/api/chat）
+    #[default]
+    #[serde(rename = "ollama")]
+    Ollama,
+    /// OpenAI 兼容接口（/chat/completions），如 llama.cpp、vLLM
+    #[serde(rename = "openai")]
+    OpenAiCompatible,
+}
+
 /// Agent 配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
+    #[serde(default)]
+    pub backend: Backend,
     pub model: String,
     pub base_url: String,
     pub max_iterations: usize,
     pub max_llm_retries: usize,
+    /// LLM 重试的基础等待时间（毫秒），第 N 次失败后等待 base × multiplier^N
+    #[serde(default = "default_retry_base_ms")]
+    pub retry_base_ms: u64,
+    /// LLM 重试等待时间的增长倍数
+    #[serde(default = "default_retry_multiplier")]
+    pub retry_multiplier: f64,
+    /// LLM 重试的最长等待时间（毫秒），计算出的等待时间不超过该值
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
     pub max_tool_calls: usize,
+    /// 同时执行的只读工具调用数上限
+    #[serde(default = "default_max_parallel_tools")]
+    pub max_parallel_tools: usize,
+    /// 是否在终端显示推理模型的思考过程（<think> 块，始终与正文分开保存）
+    #[serde(default)]
+    pub show_reasoning: bool,
+    /// 是否使用流式输出
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    /// LLM 响应体最大字节数，超过时报错
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    /// 发送给 LLM 的上下文 token 上限（估算值），超出时裁剪最早的消息
+    #[serde(default = "default_max_context_tokens")]
+    pub max_context_tokens: usize,
+    /// 后端报告上下文超长时，自动裁剪历史并重试的最大次数
+    #[serde(default = "default_max_auto_trim_retries")]
+    pub max_auto_trim_retries: usize,
+    /// 详细日志模式下终端显示工具结果的最大行数（仅影响显示，完整结果仍发送给模型）
+    #[serde(default = "default_tool_result_preview_lines")]
+    pub tool_result_preview_lines: usize,
+    /// 详细日志模式下终端显示工具结果的最大字符数
+    #[serde(default = "default_tool_result_preview_chars")]
+    pub tool_result_preview_chars: usize,
+    /// 加入上下文的单个工具结果的最大字符数，超出部分截断（0 表示不限制）
+    #[serde(default = "default_max_tool_result_chars")]
+    pub max_tool_result_chars: usize,
+    /// /compact 压缩历史时原样保留的最近对话轮数
+    #[serde(default = "default_compact_keep_turns")]
+    pub compact_keep_turns: usize,
+    /// 采样温度（未设置时使用模型默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// nucleus 采样阈值（未设置时使用模型默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// 模型上下文窗口大小（仅 Ollama，未设置时使用模型默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// 请求结束后模型在内存中保留的时长（仅 Ollama，如 "30m"，"-1" 表示一直保留；未设置时使用 Ollama 默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    /// 备用模型：主模型重试耗尽后改用该模型再请求一次（未设置时直接报错）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_model: Option<String>,
+    /// 首轮对话后自动为未命名的会话生成标题
+    #[serde(default = "default_auto_title")]
+    pub auto_title: bool,
+    /// 交互模式下等待模型响应时显示转圈提示（非终端或安静模式下不显示）
+    #[serde(default = "default_spinner")]
+    pub spinner: bool,
+    /// 终端输出中显示 emoji（为 false 时替换为 [OK] 等文字标记，与 --no-emoji 相同）
+    #[serde(default = "default_emoji")]
+    pub emoji: bool,
+    /// LLM 接口的 API 密钥，设置后以 `Authorization: Bearer` 发送（不写入会话文件）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// 附加到 LLM 请求的请求头（不写入会话文件）
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// 发送请求时替换消息的角色名（如 `tool = "function"`），用于不接受标准角色的后端；会话中保存的消息不变
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub role_map: HashMap<String, String>,
+}
+
+fn default_retry_base_ms() -> u64 {
+    100
+}
+
+fn default_retry_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_backoff_ms() -> u64 {
+    10_000
+}
+
+fn default_max_parallel_tools() -> usize {
+    4
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+fn default_max_response_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_max_context_tokens() -> usize {
+    8192
+}
+
+fn default_max_auto_trim_retries() -> usize {
+    1
+}
+
+fn default_tool_result_preview_lines() -> usize {
+    20
+}
+
+fn default_tool_result_preview_chars() -> usize {
+    2000
+}
+
+fn default_max_tool_result_chars() -> usize {
+    20000
+}
+
+fn default_compact_keep_turns() -> usize {
+    2
+}
+
+fn default_spinner() -> bool {
+    true
+}
+
+fn default_emoji() -> bool {
+    true
+}
+
+fn default_auto_title() -> bool {
+    true
 }
 
 impl Default for AgentConfig {
     fn default() -> Self {
         AgentConfig {
+            backend: Backend::default(),
             model: std::env::var("OLLAMA_MODEL")
                 .unwrap_or_else(|_| "qwen3.5:9b".to_string()),
             base_url: std::env::var("OLLAMA_URL")
                 .unwrap_or_else(|_| "http://localhost:11434".to_string()),
             max_iterations: 10,
             max_llm_retries: 3,
+            retry_base_ms: default_retry_base_ms(),
+            retry_multiplier: default_retry_multiplier(),
+            max_backoff_ms: default_max_backoff_ms(),
             max_tool_calls: 5,
+            max_parallel_tools: default_max_parallel_tools(),
+            show_reasoning: false,
+            stream: default_stream(),
+            max_response_bytes: default_max_response_bytes(),
+            max_context_tokens: default_max_context_tokens(),
+            max_auto_trim_retries: default_max_auto_trim_retries(),
+            tool_result_preview_lines: default_tool_result_preview_lines(),
+            tool_result_preview_chars: default_tool_result_preview_chars(),
+            max_tool_result_chars: default_max_tool_result_chars(),
+            compact_keep_turns: default_compact_keep_turns(),
+            temperature: None,
+            top_p: None,
+            num_ctx: None,
+            keep_alive: None,
+            fallback_model: None,
+            auto_title: default_auto_title(),
+            spinner: default_spinner(),
+            emoji: default_emoji(),
+            api_key: None,
+            headers: HashMap::new(),
+            role_map: HashMap::new(),
         }
     }
 }
 
+/// 组成系统提示的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptFile {
+    /// 文件路径，相对路径基于 workspace 根目录
+    pub file: PathBuf,
+    /// 在系统提示中的小节标题
+    pub heading: String,
+}
+
+impl PromptFile {
+    pub fn new(file: &str, heading: &str) -> Self {
+        PromptFile {
+            file: PathBuf::from(file),
+            heading: heading.to_string(),
+        }
+    }
+}
+
+fn default_prompt_files() -> Vec<PromptFile> {
+    vec![
+        PromptFile::new("AGENT.md", "角色定义"),
+        PromptFile::new("SOUL.md", "对话风格"),
+        PromptFile::new("USER.md", "用户信息"),
+    ]
+}
+
 /// Workspace 配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkspaceConfig {
     pub root: PathBuf,
-    pub agent_file: PathBuf,
-    pub soul_file: PathBuf,
-    pub user_file: PathBuf,
+    /// 按顺序拼接进系统提示的文件
+    #[serde(default = "default_prompt_files")]
+    pub prompt_files: Vec<PromptFile>,
+    /// 只读模式：不提供写操作工具，文件和记忆的写入一律拒绝
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl WorkspaceConfig {
+    /// 提示文件的完整路径
+    pub fn prompt_file_path(&self, prompt_file: &PromptFile) -> PathBuf {
+        self.root.join(&prompt_file.file)
+    }
 }
 
 impl Default for WorkspaceConfig {
     fn default() -> Self {
-        let base = default_base_dir().join("workspace");
-
         WorkspaceConfig {
-            root: base.clone(),
-            agent_file: base.join("AGENT.md"),
-            soul_file: base.join("SOUL.md"),
-            user_file: base.join("USER.md"),
+            root: data_base_dir().join("workspace"),
+            prompt_files: default_prompt_files(),
+            read_only: false,
         }
     }
 }
@@ -61,15 +296,304 @@ impl Default for WorkspaceConfig {
 pub struct SessionConfig {
     pub storage_path: PathBuf,
     pub auto_save: bool,
+    /// 把每次工具调用追加记录到会话目录的 tool_audit.jsonl
+    #[serde(default)]
+    pub audit_log: bool,
+    /// 进入交互模式时列出最近的会话供选择（等同 agent --pick）
+    #[serde(default)]
+    pub pick_on_start: bool,
+    /// 会话文件的存储格式（读取时按扩展名识别，不受此项影响）
+    #[serde(default)]
+    pub format: SessionFormat,
 }
 
 impl Default for SessionConfig {
     fn default() -> Self {
-        let base = default_base_dir().join("sessions");
+        let base = data_base_dir().join("sessions");
 
         SessionConfig {
             storage_path: base,
             auto_save: true,
+            audit_log: false,
+            pick_on_start: false,
+            format: SessionFormat::default(),
+        }
+    }
+}
+
+/// 会话文件的存储格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionFormat {
+    /// 带缩进的 JSON，便于阅读和手工修改
+    #[default]
+    JsonPretty,
+    /// 不含空白的 JSON
+    JsonCompact,
+    /// MessagePack 二进制格式，体积最小
+    Messagepack,
+}
+
+impl SessionFormat {
+    /// 会话文件的扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            SessionFormat::JsonPretty | SessionFormat::JsonCompact => "json",
+            SessionFormat::Messagepack => "msgpack",
+        }
+    }
+}
+
+/// 搜索服务提供方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchProviderKind {
+    /// 自动选择：Tavily（有密钥时）> SearXNG（已配置时）> DuckDuckGo
+    #[default]
+    Auto,
+    Tavily,
+    Searxng,
+    Duckduckgo,
+}
+
+/// Tavily 的搜索深度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchDepth {
+    #[default]
+    Basic,
+    /// 结果更相关，但耗时和 API 额度消耗更多
+    Advanced,
+}
+
+impl SearchDepth {
+    /// 请求体中的取值
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SearchDepth::Basic => "basic",
+            SearchDepth::Advanced => "advanced",
+        }
+    }
+}
+
+/// search_max_results 允许的最大值
+pub const MAX_SEARCH_RESULTS: usize = 20;
+
+/// web_fetch 提取网页文本的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchMode {
+    /// 去掉全部标签，保留页面上的所有文字
+    #[default]
+    Basic,
+    /// 只提取正文区域，保留链接（`[文本](URL)`）、标题、列表和表格结构
+    Readability,
+}
+
+impl FetchMode {
+    /// 解析工具参数中的模式名
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "basic" => Some(FetchMode::Basic),
+            "readability" => Some(FetchMode::Readability),
+            _ => None,
+        }
+    }
+}
+
+/// 工具配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolsConfig {
+    #[serde(default)]
+    pub search_provider: SearchProviderKind,
+    /// SearXNG 实例地址，如 http://localhost:8080
+    #[serde(default)]
+    pub searxng_url: Option<String>,
+    /// web_search 返回的最大结果数（1 到 20）
+    #[serde(default = "default_search_max_results")]
+    pub search_max_results: usize,
+    /// Tavily 的搜索深度（basic / advanced，其他搜索服务忽略）
+    #[serde(default)]
+    pub search_depth: SearchDepth,
+    /// web_fetch / web_search 的最大尝试次数（仅连接错误、5xx、429 时重试）
+    #[serde(default = "default_web_retries")]
+    pub web_retries: usize,
+    /// web_fetch 读取响应体的最大字节数，超出部分截断
+    #[serde(default = "default_web_fetch_max_bytes")]
+    pub web_fetch_max_bytes: usize,
+    /// web_fetch 默认的文本提取方式（工具调用可通过 mode 参数覆盖）
+    #[serde(default)]
+    pub web_fetch_mode: FetchMode,
+    /// web_fetch 结果缓存的最大条目数（按 URL，最近最少使用的先淘汰），0 表示不缓存
+    #[serde(default = "default_web_fetch_cache_size")]
+    pub web_fetch_cache_size: usize,
+    /// web_fetch 缓存的有效期（秒）
+    #[serde(default = "default_web_fetch_cache_ttl_secs")]
+    pub web_fetch_cache_ttl_secs: u64,
+    /// fs_read 读取文件的最大字节数，超出部分截断；fs_patch 拒绝修改超过该大小的文件
+    #[serde(default = "default_max_read_bytes")]
+    pub max_read_bytes: usize,
+    /// 单次工具调用的超时时间（秒）
+    #[serde(default = "default_tool_timeout_secs")]
+    pub tool_timeout_secs: u64,
+    /// 为 true 时 fs_delete 只返回"需要确认"提示，不实际删除
+    #[serde(default)]
+    pub confirm_destructive: bool,
+    /// 启用的工具白名单（未设置时启用全部内置工具）
+    #[serde(default)]
+    pub enabled: Option<Vec<String>>,
+    /// 禁用的工具（优先于 enabled）
+    #[serde(default)]
+    pub disabled: Vec<String>,
+    /// shell_exec 工具配置（默认关闭）
+    #[serde(default)]
+    pub shell: ShellConfig,
+}
+
+impl ToolsConfig {
+    /// 工具是否启用
+    pub fn is_tool_enabled(&self, name: &str) -> bool {
+        // shell_exec 必须显式开启，enabled 白名单中列出也不会启用
+        if name == "shell_exec" && !self.shell.enabled {
+            return false;
+        }
+        let allowed = self
+            .enabled
+            .as_ref()
+            .is_none_or(|enabled| enabled.iter().any(|n| n == name));
+        allowed && !self.disabled.iter().any(|n| n == name)
+    }
+}
+
+fn default_search_max_results() -> usize {
+    5
+}
+
+fn default_web_retries() -> usize {
+    3
+}
+
+fn default_web_fetch_max_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_web_fetch_cache_size() -> usize {
+    32
+}
+
+fn default_web_fetch_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_max_read_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_tool_timeout_secs() -> u64 {
+    60
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        ToolsConfig {
+            search_provider: SearchProviderKind::default(),
+            searxng_url: None,
+            search_max_results: default_search_max_results(),
+            search_depth: SearchDepth::default(),
+            web_retries: default_web_retries(),
+            web_fetch_max_bytes: default_web_fetch_max_bytes(),
+            web_fetch_mode: FetchMode::default(),
+            web_fetch_cache_size: default_web_fetch_cache_size(),
+            web_fetch_cache_ttl_secs: default_web_fetch_cache_ttl_secs(),
+            max_read_bytes: default_max_read_bytes(),
+            tool_timeout_secs: default_tool_timeout_secs(),
+            confirm_destructive: false,
+            enabled: None,
+            disabled: Vec::new(),
+            shell: ShellConfig::default(),
+        }
+    }
+}
+
+/// shell_exec 工具配置
+///
+/// 默认关闭；开启后也只能运行 allowed_commands 中列出的命令（按命令名精确匹配，
+/// 不经过 shell 解释，参数原样传递），工作目录为 workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellConfig {
+    /// 是否启用 shell_exec
+    #[serde(default)]
+    pub enabled: bool,
+    /// 允许运行的命令名，如 ["ls", "cat", "git"]；为空时任何命令都不能运行
+    #[serde(default)]
+    pub allowed_commands: Vec<String>,
+    /// 单条命令的超时时间（秒），超时后终止进程
+    #[serde(default = "default_shell_timeout_secs")]
+    pub timeout_secs: u64,
+    /// stdout / stderr 各自保留的最大字节数，超出部分截断
+    #[serde(default = "default_shell_max_output_bytes")]
+    pub max_output_bytes: usize,
+}
+
+fn default_shell_timeout_secs() -> u64 {
+    30
+}
+
+fn default_shell_max_output_bytes() -> usize {
+    64 * 1024
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        ShellConfig {
+            enabled: false,
+            allowed_commands: Vec::new(),
+            timeout_secs: default_shell_timeout_secs(),
+            max_output_bytes: default_shell_max_output_bytes(),
+        }
+    }
+}
+
+/// HTTP 配置（所有出站请求共用）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// 单个请求的总超时（秒，包含读取响应体，LLM 生成较慢时可调大）
+    #[serde(default = "default_http_timeout_secs", alias = "timeout")]
+    pub timeout_secs: u64,
+    /// 建立连接的超时（秒）
+    #[serde(default = "default_http_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// 代理地址，如 http://127.0.0.1:7890（未设置时使用 HTTP(S)_PROXY 环境变量）
+    #[serde(default, alias = "http_proxy")]
+    pub proxy: Option<String>,
+    /// User-Agent
+    #[serde(default = "default_http_user_agent")]
+    pub user_agent: String,
+    /// 附加到每个请求的请求头
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+fn default_http_timeout_secs() -> u64 {
+    300
+}
+
+fn default_http_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_http_user_agent() -> String {
+    "Mozilla/5.0 (compatible; rox-agent/1.0)".to_string()
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout_secs: default_http_timeout_secs(),
+            connect_timeout_secs: default_http_connect_timeout_secs(),
+            proxy: None,
+            user_agent: default_http_user_agent(),
+            headers: HashMap::new(),
         }
     }
 }
@@ -80,22 +604,87 @@ pub struct Config {
     pub agent: AgentConfig,
     pub workspace: WorkspaceConfig,
     pub session: SessionConfig,
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// 也可写作 `[web]`
+    #[serde(default, alias = "web")]
+    pub http: HttpConfig,
 }
 
 impl Config {
-    /// 从文件加载配置
+    /// 从文件加载配置（字符串值中的 `${VAR}` 替换为环境变量的值）
     pub fn load(path: &Path) -> Result<Self> {
         if !path.exists() {
-            return Ok(Config::default());
+            let config = Config::default();
+            config.validate()?;
+            return Ok(config);
         }
 
         let content = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)
+        let mut table: toml::Table = toml::from_str(&content)
             .with_context(|| format!("解析配置文件失败：{}", path.display()))?;
+        interpolate_env(&mut table)
+            .and_then(|_| migrate_legacy_prompt_files(&mut table))
+            .with_context(|| format!("配置文件无效：{}", path.display()))?;
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("解析配置文件失败：{}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("配置文件无效：{}", path.display()))?;
 
         Ok(config)
     }
 
+    /// 校验配置取值，错误信息中指明出错的字段
+    pub fn validate(&self) -> Result<()> {
+        let agent = &self.agent;
+        for (field, value) in [
+            ("agent.max_iterations", agent.max_iterations),
+            ("agent.max_llm_retries", agent.max_llm_retries),
+            ("agent.max_tool_calls", agent.max_tool_calls),
+            ("agent.max_parallel_tools", agent.max_parallel_tools),
+            ("tools.tool_timeout_secs", self.tools.tool_timeout_secs as usize),
+            ("tools.max_read_bytes", self.tools.max_read_bytes),
+            ("tools.shell.timeout_secs", self.tools.shell.timeout_secs as usize),
+            ("tools.shell.max_output_bytes", self.tools.shell.max_output_bytes),
+        ] {
+            if value < 1 {
+                return Err(anyhow::anyhow!("{} 必须大于等于 1，当前为 {}", field, value));
+            }
+        }
+
+        if !(agent.retry_multiplier.is_finite() && agent.retry_multiplier >= 1.0) {
+            return Err(anyhow::anyhow!(
+                "agent.retry_multiplier 必须大于等于 1，当前为 {}",
+                agent.retry_multiplier
+            ));
+        }
+
+        if !(1..=MAX_SEARCH_RESULTS).contains(&self.tools.search_max_results) {
+            return Err(anyhow::anyhow!(
+                "tools.search_max_results 必须在 1 到 {} 之间，当前为 {}",
+                MAX_SEARCH_RESULTS,
+                self.tools.search_max_results
+            ));
+        }
+
+        reqwest::Url::parse(&agent.base_url)
+            .with_context(|| format!("agent.base_url 不是合法的 URL：{}", agent.base_url))?;
+        crate::http::header_map(&agent.headers).context("agent.headers 无效")?;
+        if let Some(key) = &agent.api_key {
+            reqwest::header::HeaderValue::from_str(key).context("agent.api_key 含有非法字符")?;
+        }
+        crate::http::header_map(&self.http.headers).context("http.headers 无效")?;
+
+        // 允许列表按命令名匹配，带路径的条目可能指向 workspace 中的任意可执行文件
+        if let Some(command) = self.tools.shell.allowed_commands.iter().find(|c| c.contains(['/', '\\'])) {
+            return Err(anyhow::anyhow!("tools.shell.allowed_commands 只能填写命令名，不能包含路径：{}", command));
+        }
+
+        Ok(())
+    }
+
     /// 保存配置到文件
     pub fn save(&self, path: &Path) -> Result<()> {
         if let Some(parent) = path.parent() {
@@ -107,10 +696,35 @@ impl Config {
         Ok(())
     }
 
+    /// 只修改配置文件中的 agent.model，其余内容（包括未展开的 `${VAR}`）原样保留
+    pub fn save_model(path: &Path, model: &str) -> Result<()> {
+        if !path.exists() {
+            let mut config = Config::default();
+            config.agent.model = model.to_string();
+            return config.save(path);
+        }
+
+        let content = fs::read_to_string(path)?;
+        let mut table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("解析配置文件失败：{}", path.display()))?;
+        let agent = table
+            .entry("agent")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("配置文件中的 agent 不是表：{}", path.display()))?;
+        agent.insert("model".to_string(), toml::Value::String(model.to_string()));
+        fs::write(path, toml::to_string_pretty(&table)?)?;
+        Ok(())
+    }
+
+    /// 默认配置文件路径
+    pub fn default_path() -> PathBuf {
+        config_base_dir().join("config.toml")
+    }
+
     /// 从默认位置加载配置
     pub fn load_default() -> Result<Self> {
-        let path = default_base_dir().join("config.toml");
-        Self::load(&path)
+        Self::load(&Self::default_path())
     }
 
     /// 确保 workspace 目录存在
@@ -125,3 +739,277 @@ impl Config {
         Ok(())
     }
 }
+
+/// 旧版 workspace 中单独指定提示文件的键及其对应的 prompt_files 小节
+const LEGACY_PROMPT_KEYS: [(&str, &str); 3] = [
+    ("agent_file", "角色定义"),
+    ("soul_file", "对话风格"),
+    ("user_file", "用户信息"),
+];
+
+/// 把旧版的 `workspace.agent_file` / `soul_file` / `user_file` 映射为 `prompt_files`
+/// （未设置的小节沿用默认文件）；与 `prompt_files` 同时设置时报错
+fn migrate_legacy_prompt_files(table: &mut toml::Table) -> Result<()> {
+    let Some(toml::Value::Table(workspace)) = table.get_mut("workspace") else {
+        return Ok(());
+    };
+    let mut legacy = HashMap::new();
+    for (key, heading) in LEGACY_PROMPT_KEYS {
+        if let Some(value) = workspace.remove(key) {
+            let file = value
+                .as_str()
+                .with_context(|| format!("workspace.{} 应为文件路径字符串", key))?
+                .to_string();
+            legacy.insert(heading, (key, file));
+        }
+    }
+    if legacy.is_empty() {
+        return Ok(());
+    }
+    if workspace.contains_key("prompt_files") {
+        let mut keys: Vec<_> = legacy.values().map(|(key, _)| format!("workspace.{}", key)).collect();
+        keys.sort();
+        return Err(anyhow::anyhow!(
+            "{} 已由 workspace.prompt_files 取代，不能同时设置",
+            keys.join("、")
+        ));
+    }
+
+    let prompt_files = default_prompt_files()
+        .into_iter()
+        .map(|prompt_file| {
+            let file = match legacy.get(prompt_file.heading.as_str()) {
+                Some((_, file)) => file.clone(),
+                None => prompt_file.file.to_string_lossy().into_owned(),
+            };
+            let mut entry = toml::Table::new();
+            entry.insert("file".to_string(), toml::Value::String(file));
+            entry.insert("heading".to_string(), toml::Value::String(prompt_file.heading));
+            toml::Value::Table(entry)
+        })
+        .collect();
+    workspace.insert("prompt_files".to_string(), toml::Value::Array(prompt_files));
+    Ok(())
+}
+
+/// 配置文件中的环境变量引用
+static ENV_REF_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+/// 把配置中字符串值里的 `${VAR}` 替换为环境变量的值；在解析后的 TOML 上处理，
+/// 键名和注释不会展开，变量值中的引号等字符也不会破坏 TOML 结构。引用的变量未设置时报错
+fn interpolate_env(table: &mut toml::Table) -> Result<()> {
+    for (key, value) in table.iter_mut() {
+        interpolate_value(key, value)?;
+    }
+    Ok(())
+}
+
+/// 递归展开单个配置项，`path` 为报错时显示的配置项路径
+fn interpolate_value(path: &str, value: &mut toml::Value) -> Result<()> {
+    match value {
+        toml::Value::String(text) if text.contains("${") => {
+            let mut missing = None;
+            let expanded = ENV_REF_REGEX.replace_all(text, |caps: &regex::Captures| {
+                std::env::var(&caps[1]).unwrap_or_else(|_| {
+                    missing.get_or_insert_with(|| caps[1].to_string());
+                    String::new()
+                })
+            });
+            if let Some(name) = missing {
+                return Err(anyhow::anyhow!("配置项 {} 引用的环境变量 {} 未设置", path, name));
+            }
+            *text = expanded.into_owned();
+        }
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_value(&format!("{}[{}]", path, index), item)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                interpolate_value(&format!("{}.{}", path, key), item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 修改默认配置后校验，返回完整的错误信息
+    fn validation_error(modify: impl FnOnce(&mut Config)) -> String {
+        let mut config = Config::default();
+        modify(&mut config);
+        format!("{:#}", config.validate().unwrap_err())
+    }
+
+    /// 把默认配置写入临时文件，`workspace` 表先经 `modify` 修改，再用 `Config::load` 加载
+    fn load_with_workspace(modify: impl FnOnce(&mut toml::Table)) -> Result<Config> {
+        let mut table = toml::Table::try_from(Config::default()).unwrap();
+        let workspace = table.get_mut("workspace").and_then(|w| w.as_table_mut()).unwrap();
+        workspace.remove("prompt_files");
+        modify(workspace);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, toml::to_string(&table).unwrap()).unwrap();
+        Config::load(&path)
+    }
+
+    /// 把默认配置中 agent.model 所在的行换成 `line` 后加载
+    fn load_with_model_line(line: &str) -> Result<Config> {
+        let content: Vec<_> = toml::to_string(&Config::default())
+            .unwrap()
+            .lines()
+            .map(|l| if l.starts_with("model = ") { line.to_string() } else { l.to_string() })
+            .collect();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, content.join("\n")).unwrap();
+        Config::load(&path)
+    }
+
+    #[test]
+    fn env_value_with_quotes_stays_inside_string() {
+        std::env::set_var("ROX_TEST_QUOTED_MODEL", r#"a" = "b\c"#);
+        let config = load_with_model_line(r#"model = "${ROX_TEST_QUOTED_MODEL}""#).unwrap();
+        assert_eq!(config.agent.model, r#"a" = "b\c"#);
+    }
+
+    #[test]
+    fn missing_env_var_names_config_key() {
+        let error = load_with_model_line(r#"model = "${ROX_TEST_UNSET_MODEL}""#).unwrap_err();
+        let error = format!("{:#}", error);
+        assert!(error.contains("配置项 agent.model 引用的环境变量 ROX_TEST_UNSET_MODEL 未设置"), "{}", error);
+    }
+
+    #[test]
+    fn env_refs_in_comments_and_keys_are_not_expanded() {
+        let config = load_with_model_line(r#"model = "m" # ${ROX_TEST_UNSET_COMMENT}"#).unwrap();
+        assert_eq!(config.agent.model, "m");
+
+        let mut table: toml::Table = toml::from_str("[x]\n\"${ROX_TEST_UNSET_KEY}\" = 1").unwrap();
+        interpolate_env(&mut table).unwrap();
+        assert!(table["x"].as_table().unwrap().contains_key("${ROX_TEST_UNSET_KEY}"));
+    }
+
+    #[test]
+    fn legacy_prompt_keys_map_onto_prompt_files() {
+        let config = load_with_workspace(|w| {
+            w.insert("agent_file".into(), "/etc/rox/ROLE.md".into());
+            w.insert("user_file".into(), "ME.md".into());
+        })
+        .unwrap();
+        let files: Vec<_> = config
+            .workspace
+            .prompt_files
+            .iter()
+            .map(|p| (p.file.to_string_lossy().into_owned(), p.heading.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("/etc/rox/ROLE.md".to_string(), "角色定义"),
+                ("SOUL.md".to_string(), "对话风格"),
+                ("ME.md".to_string(), "用户信息"),
+            ]
+        );
+    }
+
+    #[test]
+    fn legacy_prompt_keys_conflict_with_prompt_files() {
+        let error = load_with_workspace(|w| {
+            w.insert("soul_file".into(), "SOUL.md".into());
+            w.insert("prompt_files".into(), toml::Value::Array(Vec::new()));
+        })
+        .unwrap_err();
+        let error = format!("{:#}", error);
+        assert!(error.contains("workspace.soul_file"), "{}", error);
+        assert!(error.contains("workspace.prompt_files"), "{}", error);
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_zero_max_iterations() {
+        let error = validation_error(|c| c.agent.max_iterations = 0);
+        assert!(error.contains("agent.max_iterations"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_zero_max_llm_retries() {
+        let error = validation_error(|c| c.agent.max_llm_retries = 0);
+        assert!(error.contains("agent.max_llm_retries"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_zero_max_tool_calls() {
+        let error = validation_error(|c| c.agent.max_tool_calls = 0);
+        assert!(error.contains("agent.max_tool_calls"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_zero_max_parallel_tools() {
+        let error = validation_error(|c| c.agent.max_parallel_tools = 0);
+        assert!(error.contains("agent.max_parallel_tools"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_zero_tool_limits() {
+        let error = validation_error(|c| c.tools.tool_timeout_secs = 0);
+        assert!(error.contains("tools.tool_timeout_secs"), "{}", error);
+        let error = validation_error(|c| c.tools.max_read_bytes = 0);
+        assert!(error.contains("tools.max_read_bytes"), "{}", error);
+        let error = validation_error(|c| c.tools.shell.timeout_secs = 0);
+        assert!(error.contains("tools.shell.timeout_secs"), "{}", error);
+        let error = validation_error(|c| c.tools.shell.max_output_bytes = 0);
+        assert!(error.contains("tools.shell.max_output_bytes"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_invalid_base_url() {
+        let error = validation_error(|c| c.agent.base_url = "localhost 11434".to_string());
+        assert!(error.contains("agent.base_url"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_retry_multiplier_below_one_or_not_finite() {
+        for multiplier in [0.5, f64::NAN, f64::INFINITY] {
+            let error = validation_error(|c| c.agent.retry_multiplier = multiplier);
+            assert!(error.contains("agent.retry_multiplier"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_search_max_results_out_of_range() {
+        for count in [0, MAX_SEARCH_RESULTS + 1] {
+            let error = validation_error(|c| c.tools.search_max_results = count);
+            assert!(error.contains("tools.search_max_results"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let error = validation_error(|c| {
+            c.agent.headers.insert("bad header".to_string(), "v".to_string());
+        });
+        assert!(error.contains("agent.headers"), "{}", error);
+        let error = validation_error(|c| {
+            c.http.headers.insert("X-Ok".to_string(), "line\nbreak".to_string());
+        });
+        assert!(error.contains("http.headers"), "{}", error);
+        let error = validation_error(|c| c.agent.api_key = Some("key\n".to_string()));
+        assert!(error.contains("agent.api_key"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_shell_commands_with_paths() {
+        let error = validation_error(|c| c.tools.shell.allowed_commands = vec!["./run.sh".to_string()]);
+        assert!(error.contains("tools.shell.allowed_commands"), "{}", error);
+    }
+}
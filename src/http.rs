@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::HttpConfig;
+
+/// 将配置中的请求头转换为 HeaderMap
+pub fn header_map(headers: &HashMap<String, String>) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("无效的请求头名称：{}", name))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("无效的请求头值：{}", value))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+/// 按配置构建 HTTP 客户端，所有出站请求（LLM、搜索、抓取、天气）共用同一套超时、代理和请求头
+pub fn build_client(config: &HttpConfig) -> Result<Client> {
+    let headers = header_map(&config.headers)?;
+
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .user_agent(&config.user_agent)
+        .default_headers(headers);
+
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy).with_context(|| format!("无效的代理地址：{}", proxy))?);
+    }
+
+    builder.build().context("创建 HTTP 客户端失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn build_client_applies_timeout() {
+        // 接受连接但从不响应
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let client = build_client(&HttpConfig { timeout_secs: 1, ..HttpConfig::default() }).unwrap();
+        let started = Instant::now();
+        let error = client.get(&url).send().await.unwrap_err();
+
+        assert!(error.is_timeout(), "{:?}", error);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(3), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn build_client_sends_user_agent_and_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+
+        let config = HttpConfig {
+            user_agent: "rox-test/1.0".to_string(),
+            headers: HashMap::from([("X-Custom".to_string(), "value".to_string())]),
+            ..HttpConfig::default()
+        };
+        build_client(&config).unwrap().get(&url).send().await.unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.contains("user-agent: rox-test/1.0"), "{}", request);
+        assert!(request.contains("x-custom: value"), "{}", request);
+    }
+
+    #[test]
+    fn build_client_rejects_invalid_proxy() {
+        let config = HttpConfig { proxy: Some("not a url".to_string()), ..HttpConfig::default() };
+        let error = build_client(&config).unwrap_err();
+        assert!(error.to_string().contains("无效的代理地址"), "{}", error);
+    }
+}
@@ -1,9 +1,13 @@
+#[macro_use]
+pub mod ui;
 pub mod config;
+pub mod http;
 pub mod types;
 pub mod agent;
 pub mod tools;
 pub mod cli;
+pub mod server;
 
-pub use config::{Config, AgentConfig, WorkspaceConfig, SessionConfig};
+pub use config::{Config, AgentConfig, Backend, HttpConfig, PromptFile, WorkspaceConfig, SessionConfig, ShellConfig, ToolsConfig};
 pub use agent::{Agent, Context};
 pub use cli::run_cli;
@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::agent::{Agent, SessionManager, SessionSummary};
+use crate::config::AgentConfig;
+
+/// 服务共享状态：Agent 一次只处理一个请求，会话统一由 SessionManager 管理
+struct ServerState {
+    agent: Agent,
+    session_manager: SessionManager,
+    /// 新建会话使用的配置
+    agent_config: AgentConfig,
+}
+
+type SharedState = Arc<Mutex<ServerState>>;
+
+#[derive(Deserialize)]
+struct ChatRequest {
+    /// 会话 ID（支持唯一前缀）；未提供时新建会话
+    session_id: Option<String>,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ChatResponse {
+    session_id: String,
+    reply: String,
+}
+
+/// 接口错误，以 `{ "error": "..." }` 返回
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl ToString) -> Self {
+        ApiError {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// HTTP 服务模式：在 127.0.0.1:port 上把 Agent 以 JSON API 的形式提供给其他应用
+/// - `POST /chat`：`{ "session_id": "可选，支持前缀", "message": "..." }` -> `{ "session_id", "reply" }`
+/// - `GET /sessions`：会话摘要列表（按最近更新时间倒序）
+pub async fn serve(agent: Agent, session_manager: SessionManager, agent_config: AgentConfig, port: u16) -> Result<()> {
+    let state = Arc::new(Mutex::new(ServerState {
+        agent,
+        session_manager,
+        agent_config,
+    }));
+
+    let app = Router::new()
+        .route("/chat", post(chat))
+        .route("/sessions", get(sessions))
+        .with_state(state);
+
+    let addr = format!("127.0.0.1:{}", port);
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("监听端口失败：{}", addr))?;
+    ui_println!("🌐 rox 服务已启动：http://{}", addr);
+    ui_println!("   POST /chat      {{\"session_id\": \"可选\", \"message\": \"...\"}}");
+    ui_println!("   GET  /sessions  列出会话");
+
+    axum::serve(listener, app).await.context("HTTP 服务异常退出")
+}
+
+async fn chat(State(state): State<SharedState>, Json(request): Json<ChatRequest>) -> Result<Json<ChatResponse>, ApiError> {
+    let message = request.message.trim();
+    if message.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "message 不能为空"));
+    }
+
+    let mut state = state.lock().await;
+    let ServerState { agent, session_manager, agent_config } = &mut *state;
+
+    let session_id = match request.session_id.as_deref() {
+        Some(prefix) => {
+            let id = session_manager
+                .resolve_id(prefix)
+                .map_err(|e| ApiError::new(StatusCode::NOT_FOUND, e))?;
+            session_manager.set_current(&id);
+            id
+        }
+        None => session_manager.create(None, agent_config.clone()).id().to_string(),
+    };
+
+    // 使用会话保存的模型和采样参数（通过 /model 切换后随会话持久化）
+    if let Some(session) = session_manager.current() {
+        let mut session_config = session.config().clone();
+        if session_config.model.is_empty() {
+            session_config.model = agent_config.model.clone();
+        }
+        agent.apply_session_config(&session_config);
+    }
+
+    info!("🌐 [{}] /chat", session_id);
+    let reply = agent
+        .chat(session_manager, message, &CancellationToken::new())
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(ChatResponse { session_id, reply }))
+}
+
+async fn sessions(State(state): State<SharedState>) -> Json<Vec<SessionSummary>> {
+    Json(state.lock().await.session_manager.summaries())
+}
@@ -1,42 +1,166 @@
 use anyhow::{Context, Result};
-use std::fs;
-use std::path::{Path, PathBuf};
+use regex::Regex;
+use similar::TextDiff;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
+/// 超过该大小的文件在详细列表中不统计行数
+const LINE_COUNT_MAX_BYTES: u64 = 1024 * 1024;
+
+/// 打包前源文件总大小上限
+const MAX_ARCHIVE_BYTES: u64 = 50 * 1024 * 1024;
+
+/// fs_tree 默认展开的层数
+pub const DEFAULT_TREE_DEPTH: usize = 3;
+
+/// fs_tree 允许的最大层数
+const MAX_TREE_DEPTH: usize = 10;
+
+/// fs_tree 最多列出的条目数
+const MAX_TREE_ENTRIES: usize = 500;
+
+/// fs_tree 只显示不展开的目录（依赖和构建产物，通常很大）
+const TREE_SKIPPED_DIRS: &[&str] = &["node_modules", "target", "__pycache__", "venv", "dist", "build"];
+
+/// 预览 diff 时改动前后保留的上下文行数
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// 只读模式下写操作返回的错误
+pub const READ_ONLY_ERROR: &str = "workspace 为只读模式";
+
+#[derive(Clone)]
 pub struct FsTools {
     workspace_root: PathBuf,
+    read_only: bool,
+    /// 读取文件的最大字节数
+    max_read_bytes: usize,
 }
 
 impl FsTools {
-    pub fn new(workspace_root: PathBuf) -> Self {
-        FsTools { workspace_root }
+    pub fn new(workspace_root: PathBuf, read_only: bool, max_read_bytes: usize) -> Self {
+        FsTools {
+            workspace_root,
+            read_only,
+            max_read_bytes,
+        }
+    }
+
+    /// 只读模式下拒绝写操作（在访问磁盘之前调用）
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(anyhow::anyhow!(READ_ONLY_ERROR));
+        }
+        Ok(())
     }
 
     /// 解析路径，确保在 workspace 内
-    fn resolve_path(&self, path: &str) -> Result<PathBuf> {
+    pub(crate) fn resolve_path(&self, path: &str) -> Result<PathBuf> {
         // 移除前导斜杠，避免绝对路径
         let clean_path = path.trim_start_matches('/');
-        
-        let full = self.workspace_root.join(clean_path);
-        
-        // 规范化路径并检查是否超出 workspace 范围
-        let canonical = full.canonicalize().unwrap_or_else(|_| full.clone());
-        
-        if canonical.starts_with(&self.workspace_root) || full.starts_with(&self.workspace_root) {
+
+        // 不访问文件系统，先按词法规范化 `.` 和 `..`，越过根目录即拒绝
+        let mut normalized = PathBuf::new();
+        for component in Path::new(clean_path).components() {
+            match component {
+                Component::Normal(part) => normalized.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return Err(anyhow::anyhow!("路径超出 workspace 范围：{}", path));
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(anyhow::anyhow!("不支持绝对路径：{}", path));
+                }
+            }
+        }
+
+        let full = self.workspace_root.join(&normalized);
+
+        // 检查最近的已存在祖先，防止通过符号链接逃逸出 workspace
+        let root = self.workspace_root
+            .canonicalize()
+            .unwrap_or_else(|_| self.workspace_root.clone());
+        let mut existing = full.as_path();
+        while existing.symlink_metadata().is_err() {
+            match existing.parent() {
+                Some(parent) => existing = parent,
+                None => return Ok(full),
+            }
+        }
+
+        let canonical = existing
+            .canonicalize()
+            .with_context(|| format!("无法解析路径：{}", path))?;
+
+        if canonical.starts_with(&root) {
             Ok(full)
         } else {
             Err(anyhow::anyhow!("路径超出 workspace 范围：{}", path))
         }
     }
 
+    /// 读取文件；超过 max_read_bytes 时只读取开头部分并附加截断提示
     pub fn read(&self, path: &str) -> Result<String> {
         let full_path = self.resolve_path(path)?;
-        let content = fs::read_to_string(&full_path)
-            .with_context(|| format!("读取文件失败：{}", path))?;
+        let display = self.display_rel(&full_path);
+        let size = fs::metadata(&full_path)
+            .with_context(|| format!("读取文件失败：{}", display))?
+            .len();
+
+        if size <= self.max_read_bytes as u64 {
+            return fs::read_to_string(&full_path)
+                .with_context(|| format!("读取文件失败：{}", display));
+        }
+
+        let mut bytes = Vec::with_capacity(self.max_read_bytes);
+        File::open(&full_path)
+            .and_then(|file| file.take(self.max_read_bytes as u64).read_to_end(&mut bytes))
+            .with_context(|| format!("读取文件失败：{}", display))?;
+
+        // 截断位置可能落在多字节字符中间，去掉末尾不完整的字符
+        let valid_len = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(anyhow::anyhow!("读取文件失败：{}（不是 UTF-8 文本）", display)),
+        };
+        bytes.truncate(valid_len);
+
+        let mut content = String::from_utf8(bytes).unwrap_or_default();
+        content.push_str(&format!(
+            "\n…(内容已截断：文件共 {} 字节，仅读取前 {} 字节)",
+            size, self.max_read_bytes
+        ));
         Ok(content)
     }
 
+    /// 批量读取多个文件，每个文件以 `=== 路径 ===` 开头；单个文件失败时在对应位置给出错误，不影响其他文件
+    pub fn read_many(&self, paths: &[String]) -> Result<String> {
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!("未指定要读取的文件"));
+        }
+
+        Ok(paths
+            .iter()
+            .map(|path| {
+                let name = match self.resolve_path(path) {
+                    Ok(full_path) => self.display_rel(&full_path),
+                    Err(_) => path.clone(),
+                };
+                let content = self.read(path).unwrap_or_else(|e| format!("❌ {}", e));
+                format!("=== {} ===\n{}", name, content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n"))
+    }
+
     pub fn write(&self, path: &str, content: &str) -> Result<String> {
+        self.ensure_writable()?;
         let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
         
         // 确保父目录存在
         if let Some(parent) = full_path.parent() {
@@ -44,15 +168,70 @@ impl FsTools {
         }
         
         fs::write(&full_path, content)
-            .with_context(|| format!("写入文件失败：{}", path))?;
-        Ok(format!("文件已写入：{}", path))
+            .with_context(|| format!("写入文件失败：{}", display))?;
+        Ok(format!("文件已写入：{}", display))
     }
 
-    pub fn patch(&self, path: &str, old_string: &str, new_string: &str) -> Result<String> {
+    pub fn append(&self, path: &str, content: &str) -> Result<String> {
+        self.ensure_writable()?;
         let full_path = self.resolve_path(path)?;
-        
+        let display = self.display_rel(&full_path);
+
+        // 确保父目录存在
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&full_path)
+            .with_context(|| format!("打开文件失败：{}", display))?;
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("追加文件失败：{}", display))?;
+        Ok(format!("已追加 {} 字节到：{}", content.len(), display))
+    }
+
+    /// 查找替换：默认要求 old_string 只出现一次；replace_all 替换全部，occurrence 只替换第 N 处（从 1 开始）；
+    /// preview 为 true 时不写入文件，只返回改动的 unified diff
+    pub fn patch(
+        &self,
+        path: &str,
+        old_string: &str,
+        new_string: &str,
+        replace_all: bool,
+        occurrence: Option<usize>,
+        preview: bool,
+    ) -> Result<String> {
+        if !preview {
+            self.ensure_writable()?;
+        }
+        if old_string.is_empty() {
+            return Err(anyhow::anyhow!("old_string 不能为空"));
+        }
+        if replace_all && occurrence.is_some() {
+            return Err(anyhow::anyhow!("replace_all 与 occurrence 不能同时使用"));
+        }
+        if occurrence == Some(0) {
+            return Err(anyhow::anyhow!("occurrence 从 1 开始计数"));
+        }
+        let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
+
+        let size = fs::metadata(&full_path)
+            .with_context(|| format!("读取文件失败：{}", display))?
+            .len();
+        if size > self.max_read_bytes as u64 {
+            return Err(anyhow::anyhow!(
+                "文件过大（{} 字节，超过 max_read_bytes {} 字节），无法修改：{}",
+                size,
+                self.max_read_bytes,
+                display
+            ));
+        }
+
         let content = fs::read_to_string(&full_path)
-            .with_context(|| format!("读取文件失败：{}", path))?;
+            .with_context(|| format!("读取文件失败：{}", display))?;
 
         // 查找 old_string 的位置
         let match_count = content.matches(old_string).count();
@@ -61,51 +240,676 @@ impl FsTools {
             return Err(anyhow::anyhow!("未找到要替换的内容：{}", old_string));
         }
 
-        if match_count > 1 {
-            return Err(anyhow::anyhow!(
-                "内容出现 {} 次，无法确定替换位置：{}",
-                match_count,
-                old_string
-            ));
-        }
+        let (new_content, replaced) = match occurrence {
+            Some(n) => {
+                let (start, _) = content.match_indices(old_string).nth(n - 1).ok_or_else(|| {
+                    anyhow::anyhow!("内容只出现 {} 次，不存在第 {} 处：{}", match_count, n, old_string)
+                })?;
+                let end = start + old_string.len();
+                (format!("{}{}{}", &content[..start], new_string, &content[end..]), 1)
+            }
+            None if replace_all => (content.replace(old_string, new_string), match_count),
+            None if match_count > 1 => {
+                return Err(anyhow::anyhow!(
+                    "内容出现 {} 次，无法确定替换位置（可设置 replace_all 或 occurrence）：{}",
+                    match_count,
+                    old_string
+                ));
+            }
+            None => (content.replacen(old_string, new_string, 1), 1),
+        };
 
-        let new_content = content.replacen(old_string, new_string, 1);
+        if preview {
+            return Ok(unified_diff(&display, &content, &new_content));
+        }
 
         fs::write(&full_path, &new_content)
-            .with_context(|| format!("写入文件失败：{}", path))?;
+            .with_context(|| format!("写入文件失败：{}", display))?;
+
+        Ok(format!("文件已更新：{}（替换 {} 处）", display, replaced))
+    }
+
+    /// 删除文件或空目录；recursive 为 true 时递归删除目录
+    pub fn delete(&self, path: &str, recursive: bool) -> Result<String> {
+        self.ensure_writable()?;
+        let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
+
+        if full_path == self.workspace_root {
+            return Err(anyhow::anyhow!("不能删除 workspace 根目录"));
+        }
 
-        Ok(format!("文件已更新：{}", path))
+        let metadata = fs::symlink_metadata(&full_path)
+            .with_context(|| format!("文件不存在：{}", display))?;
+
+        if metadata.is_dir() {
+            if recursive {
+                fs::remove_dir_all(&full_path)
+            } else {
+                fs::remove_dir(&full_path)
+            }
+            .with_context(|| format!("删除目录失败（非空目录需设置 recursive）：{}", display))?;
+            Ok(format!("目录已删除：{}", display))
+        } else {
+            fs::remove_file(&full_path)
+                .with_context(|| format!("删除文件失败：{}", display))?;
+            Ok(format!("文件已删除：{}", display))
+        }
     }
 
-    pub fn list(&self, path: &str) -> Result<String> {
+    /// 创建目录（含所有缺失的父目录）
+    pub fn create_dir(&self, path: &str) -> Result<String> {
+        self.ensure_writable()?;
         let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
+
+        if full_path.is_dir() {
+            return Ok(format!("目录已存在：{}", display));
+        }
+        if full_path.exists() {
+            return Err(anyhow::anyhow!("已存在同名文件：{}", display));
+        }
+
+        fs::create_dir_all(&full_path)
+            .with_context(|| format!("创建目录失败：{}", display))?;
+        Ok(format!("目录已创建：{}", display))
+    }
+
+    /// 列出目录内容；detailed 为 true 时附加文件大小、文本行数和子目录项数
+    pub fn list(&self, path: &str, detailed: bool) -> Result<String> {
+        let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
         
         let dir_path = Path::new(&full_path);
 
         if !dir_path.exists() {
-            return Err(anyhow::anyhow!("目录不存在：{}", path));
+            return Err(anyhow::anyhow!("目录不存在：{}", display));
         }
 
         if !dir_path.is_dir() {
-            return Err(anyhow::anyhow!("不是目录：{}", path));
+            return Err(anyhow::anyhow!("不是目录：{}", display));
         }
 
         let entries = fs::read_dir(&full_path)
-            .with_context(|| format!("读取目录失败：{}", path))?;
+            .with_context(|| format!("读取目录失败：{}", display))?;
 
         let mut items = Vec::new();
         for entry in entries {
             let entry = entry?;
             let name = entry.file_name().to_string_lossy().to_string();
             let is_dir = entry.path().is_dir();
-            items.push(if is_dir {
+            let label = if is_dir {
                 format!("📁 {}", name)
             } else {
                 format!("📄 {}", name)
-            });
+            };
+            let details = if detailed { entry_details(&entry.path(), is_dir) } else { String::new() };
+            items.push((label, details));
         }
 
         items.sort();
-        Ok(items.join("\n"))
+
+        if !detailed {
+            return Ok(items.into_iter().map(|(label, _)| label).collect::<Vec<_>>().join("\n"));
+        }
+
+        let width = items.iter().map(|(label, _)| display_width(label)).max().unwrap_or(0);
+        Ok(items
+            .into_iter()
+            .map(|(label, details)| {
+                let padding = width - display_width(&label);
+                format!("{}{}  {}", label, " ".repeat(padding), details)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// 递归列出目录树（目录在前，按缩进表示层级）；跳过隐藏项，依赖和构建目录只显示不展开，
+    /// 超过 max_depth 的子目录不再展开，总条目数达到 MAX_TREE_ENTRIES 时截断
+    pub fn tree(&self, path: &str, max_depth: usize) -> Result<String> {
+        let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
+
+        if !full_path.exists() {
+            return Err(anyhow::anyhow!("目录不存在：{}", display));
+        }
+
+        if !full_path.is_dir() {
+            return Err(anyhow::anyhow!("不是目录：{}", display));
+        }
+
+        let max_depth = max_depth.clamp(1, MAX_TREE_DEPTH);
+        let mut lines = vec![format!("{}/", display)];
+        let entries = fs::read_dir(&full_path)
+            .with_context(|| format!("读取目录失败：{}", display))?;
+        let truncated = self.tree_lines(entries, 1, max_depth, &mut lines);
+        if truncated {
+            lines.push(format!("[条目过多，仅显示前 {} 项]", MAX_TREE_ENTRIES));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// 把目录条目追加到 lines；达到条目上限时返回 true。
+    /// 无法读取的子目录或条目标记为（无法读取），不影响其余部分
+    fn tree_lines(&self, entries: fs::ReadDir, depth: usize, max_depth: usize, lines: &mut Vec<String>) -> bool {
+        let mut items = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            // 不跟随符号链接，避免循环和越出 workspace；类型未知的条目按文件排序
+            let is_dir = entry.file_type().ok().map(|t| t.is_dir());
+            items.push((is_dir != Some(true), name, entry.path(), is_dir.is_some()));
+        }
+        items.sort();
+
+        let indent = "  ".repeat(depth);
+        for (is_file, name, path, known_type) in items {
+            // 根目录占一行，其余条目计入上限
+            if lines.len() > MAX_TREE_ENTRIES {
+                return true;
+            }
+            if !known_type {
+                lines.push(format!("{}{} （无法读取）", indent, name));
+            } else if is_file {
+                lines.push(format!("{}{}", indent, name));
+            } else if TREE_SKIPPED_DIRS.contains(&name.as_str()) {
+                lines.push(format!("{}{}/ （已跳过）", indent, name));
+            } else {
+                match fs::read_dir(&path) {
+                    Err(_) => lines.push(format!("{}{}/ （无法读取）", indent, name)),
+                    Ok(children) if depth >= max_depth => {
+                        lines.push(format!("{}{}/ （{} 项）", indent, name, children.count()));
+                    }
+                    Ok(children) => {
+                        lines.push(format!("{}{}/", indent, name));
+                        if self.tree_lines(children, depth + 1, max_depth, lines) {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// 查询文件元数据，返回 JSON（exists、is_dir、size、modified）；
+    /// 路径不存在时返回 `{"exists": false}` 而不是错误，便于模型据此判断
+    pub fn stat(&self, path: &str) -> Result<String> {
+        let full_path = self.resolve_path(path)?;
+        let display = self.display_rel(&full_path);
+        let metadata = match fs::metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(serde_json::json!({ "exists": false }).to_string());
+            }
+            Err(e) => return Err(e).with_context(|| format!("读取文件信息失败：{}", display)),
+        };
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .map(|time| chrono::DateTime::<chrono::Local>::from(time).to_rfc3339());
+        Ok(serde_json::json!({
+            "exists": true,
+            "is_dir": metadata.is_dir(),
+            "size": metadata.len(),
+            "modified": modified,
+        })
+        .to_string())
+    }
+
+    /// 将文件、目录或 glob 匹配的文件打包为 workspace 内的 zip 文件
+    pub fn archive(&self, paths: &[String], output: &str) -> Result<String> {
+        self.ensure_writable()?;
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!("未指定要打包的文件"));
+        }
+
+        let output = if output.to_lowercase().ends_with(".zip") {
+            output.to_string()
+        } else {
+            format!("{}.zip", output)
+        };
+        let output_path = self.resolve_path(&output)?;
+        let output = self.display_rel(&output_path);
+
+        // 收集文件（按相对路径去重排序，跳过输出文件本身）
+        let mut files = Vec::new();
+        for path in paths {
+            if path.contains(['*', '?']) {
+                let pattern = glob_to_regex(path.trim_start_matches('/'))?;
+                let before = files.len();
+                self.collect_files(&self.workspace_root, &mut files)?;
+                let matched: Vec<PathBuf> = files
+                    .drain(before..)
+                    .filter(|f| pattern.is_match(&self.relative_name(f)))
+                    .collect();
+                if matched.is_empty() {
+                    return Err(anyhow::anyhow!("没有文件匹配：{}", path));
+                }
+                files.extend(matched);
+            } else {
+                let full_path = self.resolve_path(path)?;
+                if full_path.is_dir() {
+                    self.collect_files(&full_path, &mut files)?;
+                } else if full_path.is_file() {
+                    files.push(full_path);
+                } else {
+                    return Err(anyhow::anyhow!("文件不存在：{}", self.display_rel(&full_path)));
+                }
+            }
+        }
+        files.retain(|f| *f != output_path);
+        files.sort();
+        files.dedup();
+
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("没有可打包的文件"));
+        }
+
+        let mut total: u64 = 0;
+        for file in &files {
+            total += fs::metadata(file)?.len();
+            if total > MAX_ARCHIVE_BYTES {
+                return Err(anyhow::anyhow!(
+                    "待打包文件总大小超过上限（{} MB）",
+                    MAX_ARCHIVE_BYTES / 1024 / 1024
+                ));
+            }
+        }
+
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let archive_file = File::create(&output_path)
+            .with_context(|| format!("创建压缩包失败：{}", output))?;
+        let mut zip = ZipWriter::new(archive_file);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        for file in &files {
+            let name = self.relative_name(file);
+            zip.start_file(name.as_str(), options)
+                .with_context(|| format!("写入压缩包失败：{}", name))?;
+            let mut source = File::open(file)
+                .with_context(|| format!("读取文件失败：{}", name))?;
+            io::copy(&mut source, &mut zip)
+                .with_context(|| format!("写入压缩包失败：{}", name))?;
+        }
+        zip.finish().context("写入压缩包失败")?;
+
+        let size = fs::metadata(&output_path)?.len();
+        Ok(format!(
+            "已打包 {} 个文件到：{}（{} 字节）",
+            files.len(),
+            output,
+            size
+        ))
+    }
+
+    /// 结果和错误信息中显示的路径：相对 workspace 根目录，根目录本身显示为 `.`，
+    /// 避免把 workspace 的绝对路径写进会话记录
+    pub(crate) fn display_rel(&self, full_path: &Path) -> String {
+        let name = self.relative_name(full_path);
+        if name.is_empty() {
+            ".".to_string()
+        } else {
+            name
+        }
+    }
+
+    /// 相对 workspace 的路径，统一使用 `/` 分隔
+    pub(crate) fn relative_name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.workspace_root)
+            .unwrap_or(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// 递归收集目录下的普通文件（不跟随符号链接）
+    pub(crate) fn collect_files(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("读取目录失败：{}", self.relative_name(dir)))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.collect_files(&entry.path(), files)?;
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 改动前后内容的 unified diff（`-` 为删除行，`+` 为新增行）
+fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let text = diff
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT_LINES)
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string();
+    if text.is_empty() {
+        format!("替换前后内容相同：{}", name)
+    } else {
+        text.trim_end().to_string()
+    }
+}
+
+/// 将 glob 模式转换为正则：`**` 匹配任意层级，`*` 和 `?` 不跨越 `/`
+fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).with_context(|| format!("无效的 glob 模式：{}", pattern))
+}
+
+/// 详细列表中的元信息：目录显示项数，文件显示大小和行数（非文本或过大的文件不统计行数），
+/// 读取失败时显示占位符而不中断整个列表
+fn entry_details(path: &Path, is_dir: bool) -> String {
+    if is_dir {
+        return match fs::read_dir(path) {
+            Ok(entries) => format!("{:>9}", format!("{} 项", entries.count())),
+            Err(_) => "（无法读取）".to_string(),
+        };
+    }
+
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return "（无法读取）".to_string(),
+    };
+
+    let mut details = format!("{:>9}", format_size(size));
+    if size <= LINE_COUNT_MAX_BYTES {
+        let text = fs::read(path).ok().filter(|bytes| std::str::from_utf8(bytes).is_ok());
+        if let Some(bytes) = text {
+            details.push_str(&format!("  {:>6} 行", count_lines(&bytes)));
+        }
+    }
+    details
+}
+
+/// 终端显示宽度（近似）：CJK 等全角字符按 2 列计算
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    let newlines = bytes.iter().filter(|b| **b == b'\n').count();
+    if bytes.last().is_some_and(|b| *b != b'\n') {
+        newlines + 1
+    } else {
+        newlines
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// 临时 workspace（可写，读取上限 1 MB）
+    fn workspace() -> (TempDir, FsTools) {
+        let dir = tempfile::tempdir().unwrap();
+        let tools = FsTools::new(dir.path().to_path_buf(), false, 1024 * 1024);
+        (dir, tools)
+    }
+
+    /// 读取上限为 16 字节的临时 workspace
+    fn small_workspace() -> (TempDir, FsTools) {
+        let dir = tempfile::tempdir().unwrap();
+        let tools = FsTools::new(dir.path().to_path_buf(), false, 16);
+        (dir, tools)
+    }
+
+    #[test]
+    fn write_rejects_parent_traversal() {
+        let (_dir, tools) = workspace();
+        let error = tools.write("../../etc/passwd", "x").unwrap_err();
+        assert!(error.to_string().contains("路径超出 workspace 范围"), "{}", error);
+    }
+
+    #[test]
+    fn resolve_rejects_embedded_traversal() {
+        let (dir, tools) = workspace();
+        let error = tools.resolve_path("a/../../x").unwrap_err();
+        assert!(error.to_string().contains("路径超出 workspace 范围"), "{}", error);
+        assert!(!dir.path().parent().unwrap().join("x").exists());
+    }
+
+    #[test]
+    fn resolve_keeps_absolute_path_inside_workspace() {
+        let (dir, tools) = workspace();
+        let resolved = tools.resolve_path("/etc/passwd").unwrap();
+        assert_eq!(resolved, dir.path().join("etc/passwd"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_symlink_outside_workspace() {
+        let (dir, tools) = workspace();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+        let error = tools.write("link/secret.txt", "x").unwrap_err();
+        assert!(error.to_string().contains("路径超出 workspace 范围"), "{}", error);
+        assert!(!outside.path().join("secret.txt").exists());
+        assert!(tools.read("link").is_err());
+    }
+
+    /// zip 中的条目名（按写入顺序）
+    fn zip_entries(path: &Path) -> Vec<String> {
+        let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+        (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn archive_contains_expected_entries() {
+        let (dir, tools) = workspace();
+        tools.write("src/main.rs", "fn main() {}").unwrap();
+        tools.write("src/lib/mod.rs", "").unwrap();
+        tools.write("notes.md", "# notes").unwrap();
+        tools.write("other.txt", "skip").unwrap();
+
+        let result = tools.archive(&["src".to_string(), "*.md".to_string()], "out/backup").unwrap();
+        assert!(result.contains("已打包 3 个文件到：out/backup.zip"), "{}", result);
+
+        let mut entries = zip_entries(&dir.path().join("out/backup.zip"));
+        entries.sort();
+        assert_eq!(entries, ["notes.md", "src/lib/mod.rs", "src/main.rs"]);
+    }
+
+    #[test]
+    fn archive_output_stays_inside_workspace() {
+        let (dir, tools) = workspace();
+        tools.write("a.txt", "a").unwrap();
+
+        let error = tools.archive(&["a.txt".to_string()], "../escape.zip").unwrap_err();
+        assert!(error.to_string().contains("路径超出 workspace 范围"), "{}", error);
+        assert!(!dir.path().parent().unwrap().join("escape.zip").exists());
+    }
+
+    #[test]
+    fn archive_enforces_size_cap() {
+        let (dir, tools) = workspace();
+        // 稀疏文件，不实际占用磁盘
+        File::create(dir.path().join("big.bin"))
+            .unwrap()
+            .set_len(MAX_ARCHIVE_BYTES + 1)
+            .unwrap();
+
+        let error = tools.archive(&["big.bin".to_string()], "big.zip").unwrap_err();
+        assert!(error.to_string().contains("待打包文件总大小超过上限"), "{}", error);
+        assert!(!dir.path().join("big.zip").exists());
+    }
+
+    #[test]
+    fn write_creates_missing_file_inside_workspace() {
+        let (dir, tools) = workspace();
+        tools.write("new/dir/file.txt", "hello").unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("new/dir/file.txt")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn read_returns_whole_file_within_limit() {
+        let (dir, tools) = small_workspace();
+        fs::write(dir.path().join("under.txt"), "a".repeat(15)).unwrap();
+        fs::write(dir.path().join("exact.txt"), "a".repeat(16)).unwrap();
+
+        assert_eq!(tools.read("under.txt").unwrap(), "a".repeat(15));
+        assert_eq!(tools.read("exact.txt").unwrap(), "a".repeat(16));
+    }
+
+    #[test]
+    fn read_truncates_file_over_limit() {
+        let (dir, tools) = small_workspace();
+        fs::write(dir.path().join("over.txt"), "a".repeat(17)).unwrap();
+
+        let content = tools.read("over.txt").unwrap();
+        assert!(content.starts_with(&format!("{}\n", "a".repeat(16))), "{}", content);
+        assert!(content.contains("文件共 17 字节，仅读取前 16 字节"), "{}", content);
+    }
+
+    #[test]
+    fn patch_accepts_file_within_limit() {
+        let (dir, tools) = small_workspace();
+        fs::write(dir.path().join("exact.txt"), format!("{}b", "a".repeat(15))).unwrap();
+
+        tools.patch("exact.txt", "b", "c", false, None, false).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("exact.txt")).unwrap(), format!("{}c", "a".repeat(15)));
+    }
+
+    #[test]
+    fn patch_rejects_file_over_limit() {
+        let (dir, tools) = small_workspace();
+        let original = format!("{}b", "a".repeat(16));
+        fs::write(dir.path().join("over.txt"), &original).unwrap();
+
+        let error = tools.patch("over.txt", "b", "c", false, None, false).unwrap_err();
+        assert!(error.to_string().contains("文件过大（17 字节，超过 max_read_bytes 16 字节）"), "{}", error);
+        assert_eq!(fs::read_to_string(dir.path().join("over.txt")).unwrap(), original);
+    }
+
+    /// 在临时文件中把 `x` 替换为 `y`，成功时返回替换后的文件内容
+    fn patch_x(content: &str, replace_all: bool, occurrence: Option<usize>) -> Result<String> {
+        let (dir, tools) = workspace();
+        fs::write(dir.path().join("f.txt"), content).unwrap();
+        tools.patch("f.txt", "x", "y", replace_all, occurrence, false)?;
+        Ok(fs::read_to_string(dir.path().join("f.txt")).unwrap())
+    }
+
+    #[test]
+    fn patch_without_match_fails_in_every_mode() {
+        for (replace_all, occurrence) in [(false, None), (true, None), (false, Some(1))] {
+            let error = patch_x("abc", replace_all, occurrence).unwrap_err();
+            assert!(error.to_string().contains("未找到要替换的内容"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn patch_single_match() {
+        assert_eq!(patch_x("axb", false, None).unwrap(), "ayb");
+        assert_eq!(patch_x("axb", true, None).unwrap(), "ayb");
+        assert_eq!(patch_x("axb", false, Some(1)).unwrap(), "ayb");
+
+        let error = patch_x("axb", false, Some(2)).unwrap_err();
+        assert!(error.to_string().contains("内容只出现 1 次，不存在第 2 处"), "{}", error);
+    }
+
+    #[test]
+    fn patch_multiple_matches() {
+        let error = patch_x("x-x-x", false, None).unwrap_err();
+        assert!(error.to_string().contains("内容出现 3 次，无法确定替换位置"), "{}", error);
+
+        assert_eq!(patch_x("x-x-x", true, None).unwrap(), "y-y-y");
+        assert_eq!(patch_x("x-x-x", false, Some(2)).unwrap(), "x-y-x");
+    }
+
+    #[test]
+    fn patch_reports_replacement_count() {
+        let (dir, tools) = workspace();
+        fs::write(dir.path().join("f.txt"), "x-x-x").unwrap();
+        let result = tools.patch("f.txt", "x", "y", true, None, false).unwrap();
+        assert!(result.contains("替换 3 处"), "{}", result);
+    }
+
+    #[test]
+    fn patch_rejects_replace_all_with_occurrence() {
+        let (dir, tools) = workspace();
+        fs::write(dir.path().join("f.txt"), "x-x").unwrap();
+
+        let error = tools.patch("f.txt", "x", "y", true, Some(1), false).unwrap_err();
+        assert!(error.to_string().contains("replace_all 与 occurrence 不能同时使用"), "{}", error);
+        assert_eq!(fs::read_to_string(dir.path().join("f.txt")).unwrap(), "x-x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tree_marks_unreadable_subdirectory_and_continues() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, tools) = workspace();
+        tools.write("a.txt", "a").unwrap();
+        tools.write("locked/secret.txt", "s").unwrap();
+        tools.write("open/b.txt", "b").unwrap();
+        let locked = dir.path().join("locked");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // root 不受目录权限限制，无法构造读取失败的目录
+        let readable = fs::read_dir(&locked).is_ok();
+
+        let result = tools.tree(".", 3);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if readable {
+            return;
+        }
+        let tree = result.unwrap();
+        assert!(tree.contains("  locked/ （无法读取）"), "{}", tree);
+        assert!(tree.contains("  open/\n    b.txt"), "{}", tree);
+        assert!(tree.contains("  a.txt"), "{}", tree);
     }
 }
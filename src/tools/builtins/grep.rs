@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use regex::RegexBuilder;
+use std::fs;
+
+use super::fs::FsTools;
+
+/// 最多返回的匹配行数
+const MAX_MATCHES: usize = 100;
+
+/// 超过该大小的文件跳过
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// 单行显示的最大字符数
+const MAX_LINE_CHARS: usize = 300;
+
+/// 在 workspace 内递归搜索匹配正则的行，输出格式为 `路径:行号:内容`
+pub fn execute(fs_tools: &FsTools, pattern: &str, path: &str, case_insensitive: bool) -> Result<String> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .with_context(|| format!("无效的正则表达式：{}", pattern))?;
+
+    let full_path = fs_tools.resolve_path(path)?;
+    let mut files = Vec::new();
+    if full_path.is_dir() {
+        fs_tools.collect_files(&full_path, &mut files)?;
+    } else if full_path.is_file() {
+        files.push(full_path);
+    } else {
+        return Err(anyhow::anyhow!("路径不存在：{}", fs_tools.display_rel(&full_path)));
+    }
+    files.sort();
+
+    let mut matches = Vec::new();
+    let mut truncated = false;
+    'files: for file in &files {
+        if fs::metadata(file).map(|m| m.len() > MAX_FILE_BYTES).unwrap_or(true) {
+            continue;
+        }
+        // 非 UTF-8（二进制）文件直接跳过
+        let Ok(content) = fs::read_to_string(file) else {
+            continue;
+        };
+
+        let name = fs_tools.relative_name(file);
+        for (i, line) in content.lines().enumerate() {
+            if !regex.is_match(line) {
+                continue;
+            }
+            if matches.len() >= MAX_MATCHES {
+                truncated = true;
+                break 'files;
+            }
+            let line = if line.chars().count() > MAX_LINE_CHARS {
+                format!("{}…", line.chars().take(MAX_LINE_CHARS).collect::<String>())
+            } else {
+                line.to_string()
+            };
+            matches.push(format!("{}:{}:{}", name, i + 1, line));
+        }
+    }
+
+    if matches.is_empty() {
+        return Ok(format!("未找到匹配：{}", pattern));
+    }
+
+    let mut output = matches.join("\n");
+    if truncated {
+        output.push_str(&format!("\n[结果过多，仅显示前 {} 条匹配]", MAX_MATCHES));
+    }
+    Ok(output)
+}
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::fs::READ_ONLY_ERROR;
+
+/// 长期记忆文件名（位于 workspace 根目录，跨会话共享）
+pub const MEMORY_FILE: &str = "MEMORY.md";
+
+/// 超过该大小时提醒整理记忆
+pub const MEMORY_WARN_BYTES: usize = 4 * 1024;
+
+/// 记忆文件大小上限，超过后拒绝继续写入
+pub const MEMORY_MAX_BYTES: usize = 16 * 1024;
+
+/// 记忆文件路径
+pub fn memory_path(workspace_root: &Path) -> PathBuf {
+    workspace_root.join(MEMORY_FILE)
+}
+
+#[derive(Clone)]
+pub struct MemoryTools {
+    path: PathBuf,
+    read_only: bool,
+}
+
+impl MemoryTools {
+    pub fn new(workspace_root: &Path, read_only: bool) -> Self {
+        MemoryTools {
+            path: memory_path(workspace_root),
+            read_only,
+        }
+    }
+
+    fn load(&self) -> Result<String> {
+        if !self.path.exists() {
+            return Ok(String::new());
+        }
+        fs::read_to_string(&self.path).context("读取记忆文件失败")
+    }
+
+    fn store(&self, content: &str) -> Result<()> {
+        if self.read_only {
+            return Err(anyhow::anyhow!(READ_ONLY_ERROR));
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, content).context("写入记忆文件失败")
+    }
+
+    /// 记住一条事实（每条一行）
+    pub fn remember(&self, fact: &str) -> Result<String> {
+        let fact = fact.split_whitespace().collect::<Vec<_>>().join(" ");
+        if fact.is_empty() {
+            return Err(anyhow::anyhow!("记忆内容不能为空"));
+        }
+
+        let mut content = self.load()?;
+        if content.lines().any(|line| line.trim_start_matches("- ").trim() == fact) {
+            return Ok(format!("已存在相同记忆：{}", fact));
+        }
+
+        let entry = format!("- {}\n", fact);
+        if content.len() + entry.len() > MEMORY_MAX_BYTES {
+            return Err(anyhow::anyhow!(
+                "记忆文件已达上限（{} 字节），请先用 forget 删除过时的记忆",
+                MEMORY_MAX_BYTES
+            ));
+        }
+
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&entry);
+        self.store(&content)?;
+
+        let mut result = format!("已记住：{}", fact);
+        if content.len() > MEMORY_WARN_BYTES {
+            result.push_str(&format!(
+                "\n⚠️ 记忆文件已有 {} 字节，建议用 forget 清理过时内容",
+                content.len()
+            ));
+        }
+        Ok(result)
+    }
+
+    /// 删除包含指定文本的记忆
+    pub fn forget(&self, text: &str) -> Result<String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(anyhow::anyhow!("要删除的内容不能为空"));
+        }
+
+        let content = self.load()?;
+        let (removed, kept): (Vec<&str>, Vec<&str>) = content
+            .lines()
+            .partition(|line| line.trim_start().starts_with("- ") && line.contains(text));
+
+        if removed.is_empty() {
+            return Err(anyhow::anyhow!("未找到相关记忆：{}", text));
+        }
+
+        let mut new_content = kept.join("\n");
+        if !new_content.is_empty() {
+            new_content.push('\n');
+        }
+        self.store(&new_content)?;
+
+        Ok(format!("已删除 {} 条记忆：\n{}", removed.len(), removed.join("\n")))
+    }
+}
@@ -1,3 +1,9 @@
 pub mod fs;
 pub mod get_time;
+pub mod grep;
+pub mod memory;
+pub mod readability;
+pub mod search;
+pub mod shell;
+pub mod weather;
 pub mod web;
@@ -0,0 +1,268 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// 不属于正文的元素，整棵子树跳过
+const SKIPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form", "iframe", "svg",
+    "button",
+];
+
+/// 块级元素：前后各空一行（列表项内按行内处理，避免打断列表）
+const BLOCK_TAGS: &[&str] = &[
+    "p", "div", "section", "article", "main", "blockquote", "figure", "figcaption", "dl", "dt", "dd", "address",
+];
+
+/// 明确标记正文的元素
+static MAIN_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| Selector::parse("article, main, [role=main]").unwrap());
+
+/// 没有明确标记时的正文候选
+static CANDIDATE_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("div, section").unwrap());
+
+static BODY_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("body").unwrap());
+
+static ROW_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("tr").unwrap());
+
+static WHITESPACE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+
+static BLANK_LINES_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n{3,}").unwrap());
+
+/// 提取网页正文并转为 Markdown 风格文本：链接写成 `[文本](URL)`（相对地址按 `base_url` 补全），
+/// 保留标题、列表和表格结构；找不到任何正文时返回 None
+pub fn extract(html: &str, base_url: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let root = main_content(&document)?;
+
+    let mut writer = MarkdownWriter::new(Url::parse(base_url).ok());
+    writer.children(root);
+    let text = writer.finish();
+    (!text.is_empty()).then_some(text)
+}
+
+/// 正文区域：优先取最长的 article / main，其次取直接包含段落文字最多的 div / section，最后退回 body
+fn main_content(document: &Html) -> Option<ElementRef<'_>> {
+    if let Some(main) = document.select(&MAIN_SELECTOR).max_by_key(|e| text_len(*e)) {
+        return Some(main);
+    }
+
+    document
+        .select(&CANDIDATE_SELECTOR)
+        .map(|e| (paragraph_len(e), e))
+        .filter(|(len, _)| *len > 0)
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, e)| e)
+        .or_else(|| document.select(&BODY_SELECTOR).next())
+}
+
+fn text_len(element: ElementRef) -> usize {
+    element.text().map(|t| t.trim().chars().count()).sum()
+}
+
+/// 直接子元素中段落的文字长度
+fn paragraph_len(element: ElementRef) -> usize {
+    element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|child| child.value().name() == "p")
+        .map(text_len)
+        .sum()
+}
+
+/// 把 HTML 元素树写成 Markdown 风格文本
+struct MarkdownWriter {
+    out: String,
+    base: Option<Url>,
+    /// 当前所在的列表（由外到内），有序列表记录已输出的序号
+    lists: Vec<Option<usize>>,
+}
+
+impl MarkdownWriter {
+    fn new(base: Option<Url>) -> Self {
+        MarkdownWriter {
+            out: String::new(),
+            base,
+            lists: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> String {
+        let lines: Vec<&str> = self.out.lines().map(str::trim_end).collect();
+        BLANK_LINES_REGEX
+            .replace_all(&lines.join("\n"), "\n\n")
+            .trim()
+            .to_string()
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.text(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        if SKIPPED_TAGS.contains(&name) {
+            return;
+        }
+
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                self.block_break();
+                self.out.push_str(&"#".repeat(level));
+                self.out.push(' ');
+                self.children(element);
+                self.block_break();
+            }
+            "a" => self.link(element),
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.block_break();
+                self.out.push_str("---");
+                self.block_break();
+            }
+            "ul" | "ol" => {
+                self.lists.push((name == "ol").then_some(0));
+                self.line_break();
+                self.children(element);
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else {
+                    self.line_break();
+                }
+            }
+            "li" => {
+                self.line_break();
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", number)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.out.push_str(&"  ".repeat(depth));
+                self.out.push_str(&marker);
+                self.children(element);
+            }
+            "pre" => {
+                self.block_break();
+                let code: String = element.text().collect();
+                self.out.push_str("```\n");
+                self.out.push_str(code.trim_end_matches('\n'));
+                self.out.push_str("\n```");
+                self.block_break();
+            }
+            "code" => {
+                let code: String = element.text().collect();
+                self.out.push('`');
+                self.out.push_str(code.trim());
+                self.out.push('`');
+            }
+            "table" => self.table(element),
+            "img" => {}
+            _ if BLOCK_TAGS.contains(&name) && self.lists.is_empty() => {
+                self.block_break();
+                self.children(element);
+                self.block_break();
+            }
+            _ => self.children(element),
+        }
+    }
+
+    /// 文本节点：连续空白合并为一个空格，行首不输出空格
+    fn text(&mut self, text: &str) {
+        let collapsed = WHITESPACE_REGEX.replace_all(text, " ");
+        let at_line_start = self.out.is_empty() || self.out.ends_with(['\n', ' ']);
+        let collapsed = if at_line_start { collapsed.trim_start() } else { &collapsed };
+        self.out.push_str(collapsed);
+    }
+
+    fn link(&mut self, element: ElementRef) {
+        let mut label = MarkdownWriter::new(self.base.clone());
+        label.children(element);
+        let label = label.finish().replace('\n', " ");
+
+        match element.value().attr("href").and_then(|href| self.resolve(href)) {
+            Some(url) if !label.is_empty() => self.out.push_str(&format!("[{}]({})", label, url)),
+            _ => self.text(&label),
+        }
+    }
+
+    /// 补全相对链接，页内锚点和 javascript: 链接不保留
+    fn resolve(&self, href: &str) -> Option<String> {
+        let href = href.trim();
+        if href.is_empty() || href.starts_with('#') || href.to_lowercase().starts_with("javascript:") {
+            return None;
+        }
+        match &self.base {
+            Some(base) => base.join(href).ok().map(String::from),
+            None => Some(href.to_string()),
+        }
+    }
+
+    /// 表格写成 Markdown 表格，第一行作为表头
+    fn table(&mut self, table: ElementRef) {
+        let rows: Vec<Vec<String>> = table
+            .select(&ROW_SELECTOR)
+            .map(|row| {
+                row.children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|cell| matches!(cell.value().name(), "th" | "td"))
+                    .map(|cell| self.cell_text(cell))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+
+        self.block_break();
+        for (i, cells) in rows.iter().enumerate() {
+            self.out.push_str(&format!("| {} |\n", cells.join(" | ")));
+            if i == 0 {
+                self.out.push_str(&format!("|{}\n", " --- |".repeat(cells.len())));
+            }
+        }
+        self.block_break();
+    }
+
+    fn cell_text(&self, cell: ElementRef) -> String {
+        let mut writer = MarkdownWriter::new(self.base.clone());
+        writer.children(cell);
+        writer.finish().replace('\n', " ").replace('|', "\\|")
+    }
+
+    /// 结束当前段落（空一行）
+    fn block_break(&mut self) {
+        self.trim_trailing_spaces();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push_str(if self.out.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+    }
+
+    /// 换行（已在行首时不重复换行）
+    fn line_break(&mut self) {
+        self.trim_trailing_spaces();
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn trim_trailing_spaces(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+    }
+}
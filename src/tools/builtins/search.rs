@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Client, Url};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::config::{SearchDepth, SearchProviderKind, ToolsConfig};
+
+use super::web::{html_to_text, send_with_retry};
+
+/// 预编译正则表达式（DuckDuckGo 结果标题链接）
+static DDG_RESULT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?s)<a[^>]*class="result__a"[^>]*href="([^"]*)"[^>]*>(.*?)</a>"#).unwrap()
+});
+
+/// 预编译正则表达式（DuckDuckGo 结果摘要）
+static DDG_SNIPPET_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?s)<a[^>]*class="result__snippet"[^>]*>(.*?)</a>"#).unwrap()
+});
+
+pub type SearchFuture<'a> = Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+
+/// 搜索服务提供方
+pub trait SearchProvider: Send + Sync {
+    /// 提供方名称
+    fn name(&self) -> &str;
+
+    /// 执行搜索，返回格式化后的结果文本
+    fn search<'a>(&'a self, query: &'a str) -> SearchFuture<'a>;
+}
+
+/// 单条搜索结果
+struct SearchHit {
+    title: String,
+    url: String,
+    content: String,
+}
+
+/// 根据配置选择搜索提供方；auto 模式下有 TAVILY_API_KEY 用 Tavily，
+/// 否则配置了 searxng_url 用 SearXNG，最后回退到无需密钥的 DuckDuckGo
+pub fn provider_from_config(config: &ToolsConfig, client: &Client) -> Box<dyn SearchProvider> {
+    let retries = config.web_retries;
+    let max_results = config.search_max_results;
+    let client = client.clone();
+    let tavily = || -> Box<dyn SearchProvider> {
+        Box::new(TavilySearch::new(client.clone(), retries, max_results, config.search_depth))
+    };
+    let searxng = |url: &str| -> Box<dyn SearchProvider> {
+        Box::new(SearxngSearch::new(client.clone(), url, retries, max_results))
+    };
+    let duckduckgo = || -> Box<dyn SearchProvider> {
+        Box::new(DuckDuckGoSearch::new(client.clone(), retries, max_results))
+    };
+
+    match config.search_provider {
+        SearchProviderKind::Tavily => tavily(),
+        SearchProviderKind::Searxng => searxng(
+            config.searxng_url.as_deref().unwrap_or("http://localhost:8080"),
+        ),
+        SearchProviderKind::Duckduckgo => duckduckgo(),
+        SearchProviderKind::Auto => {
+            if std::env::var("TAVILY_API_KEY").is_ok_and(|k| !k.is_empty()) {
+                tavily()
+            } else if let Some(url) = &config.searxng_url {
+                searxng(url)
+            } else {
+                duckduckgo()
+            }
+        }
+    }
+}
+
+/// 格式化搜索结果，最多保留 max_results 条
+fn format_hits(answer: Option<&str>, hits: &[SearchHit], max_results: usize) -> String {
+    let mut output = String::new();
+
+    if let Some(answer) = answer {
+        output.push_str(&format!("摘要：{}\n\n", answer));
+    }
+
+    for (i, hit) in hits.iter().take(max_results).enumerate() {
+        output.push_str(&format!(
+            "{}. {}\n   URL: {}\n   {}\n\n",
+            i + 1,
+            hit.title,
+            hit.url,
+            hit.content
+        ));
+    }
+
+    if output.is_empty() {
+        "未找到相关结果".to_string()
+    } else {
+        output
+    }
+}
+
+/// 从 JSON 结果数组中提取 title / url / content
+fn json_hits(results: Option<&serde_json::Value>) -> Vec<SearchHit> {
+    results
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| SearchHit {
+                    title: item.get("title").and_then(|v| v.as_str()).unwrap_or("无标题").to_string(),
+                    url: item.get("url").and_then(|v| v.as_str()).unwrap_or("无 URL").to_string(),
+                    content: item.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn read_text(response: reqwest::Response) -> Result<String> {
+    let status = response.status();
+    let text = response.text().await.context("读取响应失败")?;
+
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("搜索 API 错误：{} - {}", status, text));
+    }
+    Ok(text)
+}
+
+/// Tavily 搜索（需要 TAVILY_API_KEY）
+pub struct TavilySearch {
+    client: Client,
+    retries: usize,
+    max_results: usize,
+    depth: SearchDepth,
+}
+
+impl TavilySearch {
+    pub fn new(client: Client, retries: usize, max_results: usize, depth: SearchDepth) -> Self {
+        TavilySearch {
+            client,
+            retries,
+            max_results,
+            depth,
+        }
+    }
+}
+
+impl SearchProvider for TavilySearch {
+    fn name(&self) -> &str {
+        "tavily"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let api_key = std::env::var("TAVILY_API_KEY")
+                .context("TAVILY_API_KEY 环境变量未设置")?;
+
+            let body = serde_json::json!({
+                "api_key": api_key,
+                "query": query,
+                "search_depth": self.depth.as_str(),
+                "max_results": self.max_results,
+                "include_answer": true
+            });
+
+            let response = send_with_retry(self.retries, || {
+                self.client.post("https://api.tavily.com/search").json(&body)
+            })
+            .await
+            .context("发送搜索请求失败")?;
+
+            let text = read_text(response).await?;
+            let result: serde_json::Value = serde_json::from_str(&text)
+                .with_context(|| format!("解析搜索结果失败：{}", text))?;
+
+            let answer = result.get("answer").and_then(|v| v.as_str());
+            Ok(format_hits(answer, &json_hits(result.get("results")), self.max_results))
+        })
+    }
+}
+
+/// SearXNG 搜索（自建实例，无需密钥，需开启 JSON 输出格式）
+pub struct SearxngSearch {
+    client: Client,
+    base_url: String,
+    retries: usize,
+    max_results: usize,
+}
+
+impl SearxngSearch {
+    pub fn new(client: Client, base_url: &str, retries: usize, max_results: usize) -> Self {
+        SearxngSearch {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            retries,
+            max_results,
+        }
+    }
+}
+
+impl SearchProvider for SearxngSearch {
+    fn name(&self) -> &str {
+        "searxng"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}/search", self.base_url);
+            let response = send_with_retry(self.retries, || {
+                self.client.get(&url).query(&[("q", query), ("format", "json")])
+            })
+            .await
+            .with_context(|| format!("发送搜索请求失败：{}", url))?;
+
+            let text = read_text(response).await?;
+            let result: serde_json::Value = serde_json::from_str(&text)
+                .with_context(|| format!("解析搜索结果失败：{}", text))?;
+
+            let answer = result
+                .get("answers")
+                .and_then(|v| v.as_array())
+                .and_then(|a| a.first())
+                .and_then(|v| v.as_str());
+            Ok(format_hits(answer, &json_hits(result.get("results")), self.max_results))
+        })
+    }
+}
+
+/// DuckDuckGo HTML 搜索（无需密钥）
+pub struct DuckDuckGoSearch {
+    client: Client,
+    retries: usize,
+    max_results: usize,
+}
+
+impl DuckDuckGoSearch {
+    pub fn new(client: Client, retries: usize, max_results: usize) -> Self {
+        DuckDuckGoSearch {
+            client,
+            retries,
+            max_results,
+        }
+    }
+}
+
+impl SearchProvider for DuckDuckGoSearch {
+    fn name(&self) -> &str {
+        "duckduckgo"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let response = send_with_retry(self.retries, || {
+                self.client
+                    .get("https://html.duckduckgo.com/html/")
+                    .query(&[("q", query)])
+            })
+            .await
+            .context("发送搜索请求失败")?;
+
+            let html = read_text(response).await?;
+
+            let snippets: Vec<String> = DDG_SNIPPET_REGEX
+                .captures_iter(&html)
+                .map(|c| html_to_text(&c[1]))
+                .collect();
+
+            let hits: Vec<SearchHit> = DDG_RESULT_REGEX
+                .captures_iter(&html)
+                .enumerate()
+                .map(|(i, c)| SearchHit {
+                    title: html_to_text(&c[2]),
+                    url: decode_ddg_url(&c[1]),
+                    content: snippets.get(i).cloned().unwrap_or_default(),
+                })
+                .collect();
+
+            Ok(format_hits(None, &hits, self.max_results))
+        })
+    }
+}
+
+/// DuckDuckGo 结果链接形如 //duckduckgo.com/l/?uddg=<编码后的真实 URL>
+fn decode_ddg_url(href: &str) -> String {
+    let href = href.replace("&amp;", "&");
+    let absolute = if href.starts_with("//") {
+        format!("https:{}", href)
+    } else {
+        href.clone()
+    };
+
+    Url::parse(&absolute)
+        .ok()
+        .and_then(|u| {
+            u.query_pairs()
+                .find(|(k, _)| k == "uddg")
+                .map(|(_, v)| v.into_owned())
+        })
+        .unwrap_or(href)
+}
@@ -0,0 +1,208 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+use crate::config::ShellConfig;
+
+/// shell_exec 的执行参数
+pub struct ShellOptions {
+    /// 命令的工作目录（workspace 根目录）
+    pub workspace_root: PathBuf,
+    /// 允许运行的命令名
+    pub allowed_commands: Vec<String>,
+    pub timeout: Duration,
+    /// stdout / stderr 各自保留的最大字节数
+    pub max_output_bytes: usize,
+}
+
+impl ShellOptions {
+    pub fn from_config(config: &ShellConfig, workspace_root: PathBuf) -> Self {
+        ShellOptions {
+            workspace_root,
+            allowed_commands: config.allowed_commands.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            max_output_bytes: config.max_output_bytes,
+        }
+    }
+}
+
+/// 在 workspace 中运行允许列表内的命令（不经过 shell，参数原样传递），
+/// 返回退出码和截断后的 stdout / stderr
+pub async fn execute(command: &str, args: &[String], options: &ShellOptions) -> Result<String> {
+    if command.contains(['/', '\\']) || !options.allowed_commands.iter().any(|c| c == command) {
+        return Err(anyhow::anyhow!(
+            "命令不在允许列表中：{}（允许：{}，可在 tools.shell.allowed_commands 中配置）",
+            command,
+            if options.allowed_commands.is_empty() {
+                "无".to_string()
+            } else {
+                options.allowed_commands.join(", ")
+            }
+        ));
+    }
+
+    let mut child = Command::new(command)
+        .args(args)
+        .current_dir(&options.workspace_root)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("启动命令失败：{}", command))?;
+
+    let stdout = child.stdout.take().context("无法读取命令输出")?;
+    let stderr = child.stderr.take().context("无法读取命令输出")?;
+    let max = options.max_output_bytes;
+
+    let run = async {
+        let (stdout, stderr, status) = tokio::join!(
+            read_capped(stdout, max),
+            read_capped(stderr, max),
+            child.wait()
+        );
+        Ok::<_, anyhow::Error>((stdout?, stderr?, status?))
+    };
+
+    // 超时后 child 随 future 一起被丢弃，kill_on_drop 会终止进程
+    let ((stdout, stdout_truncated), (stderr, stderr_truncated), status) =
+        tokio::time::timeout(options.timeout, run)
+            .await
+            .map_err(|_| anyhow::anyhow!("命令执行超时（{} 秒），已终止：{}", options.timeout.as_secs(), command))?
+            .with_context(|| format!("执行命令失败：{}", command))?;
+
+    let exit_code = status
+        .code()
+        .map(|code| code.to_string())
+        .unwrap_or_else(|| "无（被信号终止）".to_string());
+
+    let mut output = format!("退出码：{}", exit_code);
+    for (label, bytes, truncated) in [
+        ("标准输出", stdout, stdout_truncated),
+        ("标准错误", stderr, stderr_truncated),
+    ] {
+        if bytes.is_empty() {
+            continue;
+        }
+        output.push_str(&format!("\n\n{}：\n{}", label, String::from_utf8_lossy(&bytes).trim_end()));
+        if truncated {
+            output.push_str("\n…(输出已截断)");
+        }
+    }
+    Ok(output)
+}
+
+/// 读取全部输出但只保留前 max_bytes 字节，返回（内容, 是否截断）；
+/// 超出部分继续读取并丢弃，避免管道写满导致子进程阻塞
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, max_bytes: usize) -> Result<(Vec<u8>, bool)> {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await.context("读取命令输出失败")?;
+        if n == 0 {
+            return Ok((kept, truncated));
+        }
+        let remaining = max_bytes - kept.len();
+        if n > remaining {
+            truncated = true;
+        }
+        kept.extend_from_slice(&buf[..n.min(remaining)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToolsConfig;
+    use crate::tools::registry::{enabled_tools, ensure_enabled};
+
+    fn options(workspace_root: &std::path::Path, allowed: &[&str], timeout_secs: u64) -> ShellOptions {
+        ShellOptions {
+            workspace_root: workspace_root.to_path_buf(),
+            allowed_commands: allowed.iter().map(|c| c.to_string()).collect(),
+            timeout: Duration::from_secs(timeout_secs),
+            max_output_bytes: 1024,
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn shell_exec_is_disabled_by_default() {
+        let tools = enabled_tools(&ToolsConfig::default(), false);
+        let error = ensure_enabled(&tools, "shell_exec", false).unwrap_err();
+        assert!(error.to_string().contains("shell_exec 未启用"), "{}", error);
+
+        let mut config = ToolsConfig::default();
+        config.shell.enabled = true;
+        ensure_enabled(&enabled_tools(&config, false), "shell_exec", false).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_command_outside_allowlist() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = execute("rm", &args(&["-rf", "x"]), &options(dir.path(), &["ls"], 5)).await.unwrap_err();
+        assert!(error.to_string().contains("命令不在允许列表中：rm（允许：ls"), "{}", error);
+
+        let error = execute("ls", &[], &options(dir.path(), &[], 5)).await.unwrap_err();
+        assert!(error.to_string().contains("允许：无"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_command_paths_even_when_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path(), &["ls", "./ls", "/bin/ls", "bin\\ls"], 5);
+        for command in ["./ls", "/bin/ls", "bin\\ls"] {
+            let error = execute(command, &[], &options).await.unwrap_err();
+            assert!(error.to_string().contains("命令不在允许列表中"), "{}: {}", command, error);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn runs_allowed_command_in_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("marker.txt"), "").unwrap();
+        let output = execute("ls", &[], &options(dir.path(), &["ls"], 5)).await.unwrap();
+        assert!(output.starts_with("退出码：0"), "{}", output);
+        assert!(output.contains("marker.txt"), "{}", output);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn timeout_kills_child() {
+        let dir = tempfile::tempdir().unwrap();
+        let started = std::time::Instant::now();
+        let error = execute("sh", &args(&["-c", "echo $$ > pid; exec sleep 30"]), &options(dir.path(), &["sh"], 1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("命令执行超时（1 秒），已终止：sh"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // 进程已被终止：/proc 中不存在或只剩僵尸状态
+        let pid = std::fs::read_to_string(dir.path().join("pid")).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+    }
+
+    #[tokio::test]
+    async fn read_capped_truncates_and_flags() {
+        let (kept, truncated) = read_capped(&b"hello world"[..], 5).await.unwrap();
+        assert_eq!((kept.as_slice(), truncated), (&b"hello"[..], true));
+
+        let (kept, truncated) = read_capped(&b"hello"[..], 5).await.unwrap();
+        assert_eq!((kept.as_slice(), truncated), (&b"hello"[..], false));
+
+        // 超过读取缓冲区大小时仍继续读完并丢弃
+        let data = vec![b'x'; 20_000];
+        let (kept, truncated) = read_capped(data.as_slice(), 100).await.unwrap();
+        assert_eq!((kept.len(), truncated), (100, true));
+    }
+}
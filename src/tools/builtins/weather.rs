@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde_json::Value;
+
+/// Open-Meteo 地理编码接口（可通过 WEATHER_GEOCODING_URL 覆盖）
+const DEFAULT_GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+
+/// Open-Meteo 天气接口（可通过 WEATHER_FORECAST_URL 覆盖）
+const DEFAULT_FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+pub async fn execute(client: &Client, location: &str) -> Result<String> {
+    let geocoding_url = std::env::var("WEATHER_GEOCODING_URL")
+        .unwrap_or_else(|_| DEFAULT_GEOCODING_URL.to_string());
+    let forecast_url = std::env::var("WEATHER_FORECAST_URL")
+        .unwrap_or_else(|_| DEFAULT_FORECAST_URL.to_string());
+
+    // 1. 地名 -> 经纬度
+    let geo: Value = get_json(
+        client,
+        &geocoding_url,
+        &[
+            ("name", location),
+            ("count", "1"),
+            ("language", "zh"),
+            ("format", "json"),
+        ],
+    )
+    .await
+    .context("查询地点失败")?;
+
+    let place = geo
+        .get("results")
+        .and_then(|v| v.as_array())
+        .and_then(|r| r.first())
+        .with_context(|| format!("未找到地点：{}", location))?;
+
+    let latitude = place.get("latitude").and_then(|v| v.as_f64()).context("地点缺少纬度")?;
+    let longitude = place.get("longitude").and_then(|v| v.as_f64()).context("地点缺少经度")?;
+
+    let name = [
+        place.get("name").and_then(|v| v.as_str()),
+        place.get("admin1").and_then(|v| v.as_str()),
+        place.get("country").and_then(|v| v.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(", ");
+
+    // 2. 经纬度 -> 当前天气
+    let latitude = latitude.to_string();
+    let longitude = longitude.to_string();
+    let forecast: Value = get_json(
+        client,
+        &forecast_url,
+        &[
+            ("latitude", latitude.as_str()),
+            ("longitude", longitude.as_str()),
+            (
+                "current",
+                "temperature_2m,apparent_temperature,relative_humidity_2m,weather_code,wind_speed_10m",
+            ),
+            ("timezone", "auto"),
+        ],
+    )
+    .await
+    .context("查询天气失败")?;
+
+    let current = forecast.get("current").context("天气数据缺少 current 字段")?;
+    let number = |key: &str| current.get(key).and_then(|v| v.as_f64());
+
+    let mut output = format!("{} 当前天气：", name);
+    if let Some(code) = current.get("weather_code").and_then(|v| v.as_u64()) {
+        output.push_str(describe_weather_code(code));
+    }
+    if let Some(t) = number("temperature_2m") {
+        output.push_str(&format!("，气温 {:.1}°C", t));
+    }
+    if let Some(t) = number("apparent_temperature") {
+        output.push_str(&format!("（体感 {:.1}°C）", t));
+    }
+    if let Some(h) = number("relative_humidity_2m") {
+        output.push_str(&format!("，湿度 {:.0}%", h));
+    }
+    if let Some(w) = number("wind_speed_10m") {
+        output.push_str(&format!("，风速 {:.1} km/h", w));
+    }
+
+    Ok(output)
+}
+
+async fn get_json(client: &Client, url: &str, query: &[(&str, &str)]) -> Result<Value> {
+    let response = client
+        .get(url)
+        .query(query)
+        .send()
+        .await
+        .with_context(|| format!("请求 URL 失败：{}", url))?;
+
+    let status = response.status();
+    let text = response.text().await.context("读取响应失败")?;
+
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("天气 API 错误：{} - {}", status, text));
+    }
+
+    serde_json::from_str(&text).with_context(|| format!("解析天气数据失败：{}", text))
+}
+
+/// WMO 天气代码 -> 中文描述
+fn describe_weather_code(code: u64) -> &'static str {
+    match code {
+        0 => "晴",
+        1 => "大部晴朗",
+        2 => "局部多云",
+        3 => "阴",
+        45 | 48 => "雾",
+        51 | 53 | 55 => "毛毛雨",
+        56 | 57 => "冻毛毛雨",
+        61 => "小雨",
+        63 => "中雨",
+        65 => "大雨",
+        66 | 67 => "冻雨",
+        71 => "小雪",
+        73 => "中雪",
+        75 => "大雪",
+        77 => "雪粒",
+        80..=82 => "阵雨",
+        85 | 86 => "阵雪",
+        95 => "雷暴",
+        96 | 99 => "雷暴伴冰雹",
+        _ => "未知天气",
+    }
+}
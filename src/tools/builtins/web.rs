@@ -1,7 +1,14 @@
 use anyhow::{Context, Result};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::{FetchMode, ToolsConfig};
+
+use super::readability;
 
 /// 预编译正则表达式（移除 script 标签）
 static SCRIPT_REGEX: Lazy<Regex> =
@@ -19,86 +26,205 @@ static HTML_TAG_REGEX: Lazy<Regex> =
 static MULTIPLE_NEWLINES_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"\n\s*\n").unwrap());
 
-pub async fn search(query: &str) -> Result<String> {
-    // 使用 Tavily 搜索 API
-    let api_key = std::env::var("TAVILY_API_KEY")
-        .context("TAVILY_API_KEY 环境变量未设置")?;
+/// 带重试发送请求：仅在连接错误、5xx 或 429 时重试，4xx 直接返回
+///
+/// 退避时间为指数增长（200ms、400ms、800ms...）加上少量随机抖动。
+pub(crate) async fn send_with_retry<F>(attempts: usize, build: F) -> Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let attempts = attempts.max(1);
+    let mut last_error = None;
 
-    let client = Client::new();
-    let url = "https://api.tavily.com/search";
+    for attempt in 1..=attempts {
+        match build().send().await {
+            Ok(response) if !is_retryable_status(response.status()) || attempt == attempts => {
+                return Ok(response);
+            }
+            Ok(response) => {
+                last_error = Some(anyhow::anyhow!("服务端返回 {}", response.status()));
+            }
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                last_error = Some(e.into());
+            }
+            Err(e) => return Err(e.into()),
+        }
 
-    let body = serde_json::json!({
-        "api_key": api_key,
-        "query": query,
-        "search_depth": "basic",
-        "include_answer": true
-    });
+        if attempt < attempts {
+            let backoff = 200 * (1u64 << (attempt - 1));
+            tokio::time::sleep(Duration::from_millis(backoff + jitter_millis(backoff / 2))).await;
+        }
+    }
 
-    let response = client
-        .post(url)
-        .json(&body)
-        .send()
-        .await
-        .context("发送搜索请求失败")?;
+    Err(last_error
+        .unwrap_or_else(|| anyhow::anyhow!("请求失败"))
+        .context(format!("请求在 {} 次尝试后仍然失败", attempts)))
+}
 
-    let status = response.status();
-    let text = response.text().await.context("读取响应失败")?;
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
 
-    if !status.is_success() {
-        return Err(anyhow::anyhow!("搜索 API 错误：{} - {}", status, text));
+/// 0..max 之间的随机抖动（基于系统时间纳秒，无需额外依赖）
+fn jitter_millis(max: u64) -> u64 {
+    if max == 0 {
+        return 0;
     }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    nanos % max
+}
 
-    let result: serde_json::Value = serde_json::from_str(&text)
-        .with_context(|| format!("解析搜索结果失败：{}", text))?;
-
-    // 提取搜索结果
-    let mut output = String::new();
+/// web_fetch 的请求参数
+pub struct FetchOptions {
+    pub client: Client,
+    /// 最大尝试次数
+    pub retries: usize,
+    /// 读取响应体的最大字节数
+    pub max_bytes: usize,
+    /// 默认的文本提取方式
+    pub mode: FetchMode,
+    /// 抓取结果缓存（只用于 web_fetch，不与搜索共用）
+    pub cache: FetchCache,
+}
 
-    if let Some(answer) = result.get("answer").and_then(|v| v.as_str()) {
-        output.push_str(&format!("摘要：{}\n\n", answer));
+impl FetchOptions {
+    pub fn from_config(config: &ToolsConfig, client: &Client) -> Self {
+        FetchOptions {
+            client: client.clone(),
+            retries: config.web_retries,
+            max_bytes: config.web_fetch_max_bytes,
+            mode: config.web_fetch_mode,
+            cache: FetchCache::new(
+                config.web_fetch_cache_size,
+                Duration::from_secs(config.web_fetch_cache_ttl_secs),
+            ),
+        }
     }
+}
+
+/// 按 URL 和提取方式缓存清理后的网页文本：超过有效期的条目失效，超出容量时淘汰最近最少使用的条目
+pub struct FetchCache {
+    capacity: usize,
+    ttl: Duration,
+    /// 按最近使用顺序排列，末尾为最近使用
+    entries: Mutex<VecDeque<CacheEntry>>,
+}
 
-    if let Some(results) = result.get("results").and_then(|v| v.as_array()) {
-        for (i, item) in results.iter().take(5).enumerate() {
-            let title = item.get("title").and_then(|v| v.as_str()).unwrap_or("无标题");
-            let url = item.get("url").and_then(|v| v.as_str()).unwrap_or("无 URL");
-            let content = item.get("content").and_then(|v| v.as_str()).unwrap_or("");
+struct CacheEntry {
+    url: String,
+    mode: FetchMode,
+    text: String,
+    fetched_at: Instant,
+}
 
-            output.push_str(&format!("{}. {}\n   URL: {}\n   {}\n\n", i + 1, title, url, content));
+impl FetchCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        FetchCache {
+            capacity,
+            ttl,
+            entries: Mutex::new(VecDeque::new()),
         }
     }
 
-    if output.is_empty() {
-        Ok("未找到相关结果".to_string())
-    } else {
-        Ok(output)
+    /// 取出未过期的缓存内容
+    pub fn get(&self, url: &str, mode: FetchMode) -> Option<String> {
+        let mut entries = self.entries.lock().ok()?;
+        let index = entries.iter().position(|e| e.url == url && e.mode == mode)?;
+        let entry = entries.remove(index)?;
+        if entry.fetched_at.elapsed() > self.ttl {
+            return None;
+        }
+        let text = entry.text.clone();
+        entries.push_back(entry);
+        Some(text)
+    }
+
+    pub fn insert(&self, url: &str, mode: FetchMode, text: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        entries.retain(|e| e.url != url || e.mode != mode);
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(CacheEntry {
+            url: url.to_string(),
+            mode,
+            text: text.to_string(),
+            fetched_at: Instant::now(),
+        });
     }
 }
 
-pub async fn fetch(url: &str) -> Result<String> {
-    let client = Client::new();
+/// 按 `mode` 抓取网页并提取文本；`no_cache` 为 true 时跳过缓存直接请求（结果仍会写入缓存）
+pub async fn fetch(url: &str, mode: FetchMode, options: &FetchOptions, no_cache: bool) -> Result<String> {
+    let parsed = Url::parse(url).with_context(|| format!("无效的 URL：{}", url))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!("仅支持 http/https 协议：{}", url));
+    }
 
-    let response = client
-        .get(url)
-        .header("User-Agent", "Mozilla/5.0 (compatible; rox-agent/1.0)")
-        .send()
-        .await
-        .with_context(|| format!("请求 URL 失败：{}", url))?;
+    if !no_cache {
+        if let Some(text) = options.cache.get(url, mode) {
+            tracing::debug!("📦 使用缓存的网页内容：{}", url);
+            return Ok(text);
+        }
+    }
 
-    let status = response.status();
-    let text = response.text().await.context("读取响应失败")?;
+    let response = send_with_retry(options.retries, || options.client.get(url))
+    .await
+    .with_context(|| format!("请求 URL 失败：{}", url))?;
 
+    let status = response.status();
     if !status.is_success() {
         return Err(anyhow::anyhow!("网页请求错误：{} - {}", status, url));
     }
 
-    // 简单清理 HTML，提取文本
-    let plain_text = html_to_text(&text);
+    // 重定向后的地址用于补全相对链接
+    let final_url = response.url().to_string();
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v.contains("html"));
+    let (body, truncated) = read_limited(response, options.max_bytes).await?;
+    let body = String::from_utf8_lossy(&body);
+
+    // 正文提取只用于 HTML，提取不到内容时退回简单清理
+    let mut plain_text = match mode {
+        FetchMode::Readability if is_html => readability::extract(&body, &final_url)
+            .unwrap_or_else(|| html_to_text(&body)),
+        _ => html_to_text(&body),
+    };
+    if truncated {
+        plain_text.push_str("\n…(内容已截断)");
+    }
 
+    options.cache.insert(url, mode, &plain_text);
     Ok(plain_text)
 }
 
-fn html_to_text(html: &str) -> String {
+/// 分块读取响应体，超过 max_bytes 时停止读取，返回（内容, 是否截断）
+async fn read_limited(mut response: Response, max_bytes: usize) -> Result<(Vec<u8>, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.context("读取响应失败")? {
+        let remaining = max_bytes - body.len();
+        // 恰好读满时继续读下一块，确认确实还有剩余内容才标记截断
+        if chunk.len() > remaining {
+            body.extend_from_slice(&chunk[..remaining]);
+            return Ok((body, true));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, false))
+}
+
+pub(crate) fn html_to_text(html: &str) -> String {
     let mut result = html.to_string();
 
     // 移除 script 和 style 标签
@@ -122,3 +248,51 @@ fn html_to_text(html: &str) -> String {
 
     result.trim().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 本地 HTTP 服务：对每个请求返回给定的响应体
+    async fn body_server(body: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        url
+    }
+
+    async fn read(body: &str, max_bytes: usize) -> (Vec<u8>, bool) {
+        let url = body_server(body.to_string()).await;
+        let response = reqwest::get(&url).await.unwrap();
+        read_limited(response, max_bytes).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_limited_keeps_body_of_exact_size() {
+        assert_eq!(read("x".repeat(1024).as_str(), 1024).await, (vec![b'x'; 1024], false));
+    }
+
+    #[tokio::test]
+    async fn read_limited_marks_longer_body_truncated() {
+        assert_eq!(read("x".repeat(1025).as_str(), 1024).await, (vec![b'x'; 1024], true));
+    }
+
+    #[tokio::test]
+    async fn read_limited_keeps_shorter_body() {
+        assert_eq!(read("hello", 1024).await, (b"hello".to_vec(), false));
+    }
+}
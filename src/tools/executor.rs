@@ -1,32 +1,42 @@
 use anyhow::{Context, Result};
+use reqwest::Client;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::config::{FetchMode, ToolsConfig};
 use crate::types::Tool;
 
-use super::builtins::{fs::FsTools, get_time, web};
+use super::builtins::search::{provider_from_config, SearchProvider};
+use super::builtins::shell::{self, ShellOptions};
+use super::builtins::web::FetchOptions;
+use super::builtins::fs::{FsTools, DEFAULT_TREE_DEPTH};
+use super::builtins::{get_time, grep, memory::MemoryTools, weather, web};
+use super::registry::{enabled_tools, ensure_enabled};
 
-/// 工具执行器 - 直接持有 FsTools，避免不必要的抽象层
+/// 工具执行器 - 直接持有各内置工具，避免不必要的抽象层
 pub struct ToolExecutor {
-    fs_tools: FsTools,
+    tools: Vec<Tool>,
+    local_tools: LocalTools,
+    search_provider: Box<dyn SearchProvider>,
+    fetch_options: FetchOptions,
+    shell_options: ShellOptions,
+    client: Client,
+    timeout: Duration,
+    read_only: bool,
 }
 
-impl ToolExecutor {
-    pub fn new(workspace_root: PathBuf) -> Self {
-        ToolExecutor {
-            fs_tools: FsTools::new(workspace_root),
-        }
-    }
-
-    /// 获取所有工具定义
-    pub fn get_tools(&self) -> &[Tool] {
-        // 引用 registry 中定义的静态工具列表
-        use super::registry::get_tools_static;
-        get_tools_static()
-    }
+/// 同步执行的本地工具（文件和记忆），放到阻塞线程池中运行以便同样受超时约束
+#[derive(Clone)]
+struct LocalTools {
+    fs_tools: FsTools,
+    memory_tools: MemoryTools,
+    confirm_destructive: bool,
+}
 
-    pub async fn execute(&self, name: &str, args: &HashMap<String, Value>) -> Result<String> {
+impl LocalTools {
+    fn execute(&self, name: &str, args: &HashMap<String, Value>) -> Result<String> {
         match name {
             "fs_read" => {
                 let path = args
@@ -35,6 +45,16 @@ impl ToolExecutor {
                     .context("缺少 path 参数")?;
                 self.fs_tools.read(path)
             }
+            "fs_read_many" => {
+                let paths: Vec<String> = args
+                    .get("paths")
+                    .and_then(|v| v.as_array())
+                    .context("缺少 paths 参数")?
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect();
+                self.fs_tools.read_many(&paths)
+            }
             "fs_write" => {
                 let path = args
                     .get("path")
@@ -46,6 +66,17 @@ impl ToolExecutor {
                     .context("缺少 content 参数")?;
                 self.fs_tools.write(path, content)
             }
+            "fs_append" => {
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 path 参数")?;
+                let content = args
+                    .get("content")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 content 参数")?;
+                self.fs_tools.append(path, content)
+            }
             "fs_patch" => {
                 let path = args
                     .get("path")
@@ -59,31 +90,220 @@ impl ToolExecutor {
                     .get("new_string")
                     .and_then(|v| v.as_str())
                     .context("缺少 new_string 参数")?;
-                self.fs_tools.patch(path, old_string, new_string)
+                let replace_all = args
+                    .get("replace_all")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let occurrence = args
+                    .get("occurrence")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize);
+                let preview = args
+                    .get("preview")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                self.fs_tools.patch(path, old_string, new_string, replace_all, occurrence, preview)
+            }
+            "fs_delete" => {
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 path 参数")?;
+                let recursive = args
+                    .get("recursive")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                if self.confirm_destructive {
+                    return Ok(format!(
+                        "需要确认：删除 {} 需要用户明确允许（confirm_destructive 已开启），未执行删除",
+                        path
+                    ));
+                }
+                self.fs_tools.delete(path, recursive)
+            }
+            "fs_archive" => {
+                let paths: Vec<String> = args
+                    .get("paths")
+                    .and_then(|v| v.as_array())
+                    .context("缺少 paths 参数")?
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect();
+                let output = args
+                    .get("output")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 output 参数")?;
+                self.fs_tools.archive(&paths, output)
+            }
+            "fs_mkdir" => {
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 path 参数")?;
+                self.fs_tools.create_dir(path)
             }
             "fs_list" => {
                 let path = args
                     .get("path")
                     .and_then(|v| v.as_str())
                     .context("缺少 path 参数")?;
-                self.fs_tools.list(path)
+                let detailed = args
+                    .get("detailed")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                self.fs_tools.list(path, detailed)
             }
+            "fs_tree" => {
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(".");
+                let max_depth = args
+                    .get("max_depth")
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as usize)
+                    .unwrap_or(DEFAULT_TREE_DEPTH);
+                self.fs_tools.tree(path, max_depth)
+            }
+            "fs_stat" => {
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 path 参数")?;
+                self.fs_tools.stat(path)
+            }
+            "fs_grep" => {
+                let pattern = args
+                    .get("pattern")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 pattern 参数")?;
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(".");
+                let case_insensitive = args
+                    .get("case_insensitive")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                grep::execute(&self.fs_tools, pattern, path, case_insensitive)
+            }
+            "remember" => {
+                let fact = args
+                    .get("fact")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 fact 参数")?;
+                self.memory_tools.remember(fact)
+            }
+            "forget" => {
+                let text = args
+                    .get("text")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 text 参数")?;
+                self.memory_tools.forget(text)
+            }
+            _ => Err(anyhow::anyhow!("未知工具：{}", name)),
+        }
+    }
+}
+
+impl ToolExecutor {
+    pub fn new(workspace_root: PathBuf, read_only: bool, tools_config: &ToolsConfig, client: &Client) -> Self {
+        ToolExecutor {
+            tools: enabled_tools(tools_config, read_only),
+            local_tools: LocalTools {
+                memory_tools: MemoryTools::new(&workspace_root, read_only),
+                confirm_destructive: tools_config.confirm_destructive,
+                fs_tools: FsTools::new(workspace_root.clone(), read_only, tools_config.max_read_bytes),
+            },
+            search_provider: provider_from_config(tools_config, client),
+            fetch_options: FetchOptions::from_config(tools_config, client),
+            shell_options: ShellOptions::from_config(&tools_config.shell, workspace_root),
+            client: client.clone(),
+            timeout: Duration::from_secs(tools_config.tool_timeout_secs),
+            read_only,
+        }
+    }
+
+    /// 获取已启用的工具定义
+    pub fn get_tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    /// 按名称查找已启用的工具定义
+    pub fn tool_definition(&self, name: &str) -> Option<&Tool> {
+        self.tools.iter().find(|t| t.function.name == name)
+    }
+
+    /// 执行工具调用，超过 tool_timeout_secs 时返回超时错误
+    /// （阻塞线程中的同步工具无法被强制中止，超时后结果会被丢弃）
+    pub async fn execute(&self, name: &str, args: &HashMap<String, Value>) -> Result<String> {
+        ensure_enabled(&self.tools, name, self.read_only)?;
+
+        tokio::time::timeout(self.timeout, self.dispatch(name, args))
+            .await
+            .unwrap_or_else(|_| {
+                Err(anyhow::anyhow!("工具执行超时（{} 秒）：{}", self.timeout.as_secs(), name))
+            })
+    }
+
+    async fn dispatch(&self, name: &str, args: &HashMap<String, Value>) -> Result<String> {
+        match name {
             "web_search" => {
                 let query = args
                     .get("query")
                     .and_then(|v| v.as_str())
                     .context("缺少 query 参数")?;
-                web::search(query).await
+                self.search_provider.search(query).await
             }
             "web_fetch" => {
                 let url = args
                     .get("url")
                     .and_then(|v| v.as_str())
                     .context("缺少 url 参数")?;
-                web::fetch(url).await
+                let no_cache = args
+                    .get("no_cache")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let mode = match args.get("mode").and_then(|v| v.as_str()) {
+                    Some(name) => FetchMode::parse(name)
+                        .with_context(|| format!("无效的 mode 参数：{}（可选 basic、readability）", name))?,
+                    None => self.fetch_options.mode,
+                };
+                web::fetch(url, mode, &self.fetch_options, no_cache).await
+            }
+            "shell_exec" => {
+                let command = args
+                    .get("command")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 command 参数")?;
+                let command_args: Vec<String> = args
+                    .get("args")
+                    .and_then(|v| v.as_array())
+                    .map(|items| {
+                        items
+                            .iter()
+                            .filter_map(|v| v.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                shell::execute(command, &command_args, &self.shell_options).await
             }
             "get_time" => Ok(get_time::execute()),
-            _ => Err(anyhow::anyhow!("未知工具：{}", name)),
+            "get_weather" => {
+                let location = args
+                    .get("location")
+                    .and_then(|v| v.as_str())
+                    .context("缺少 location 参数")?;
+                weather::execute(&self.client, location).await
+            }
+            _ => {
+                let local_tools = self.local_tools.clone();
+                let name = name.to_string();
+                let args = args.clone();
+                tokio::task::spawn_blocking(move || local_tools.execute(&name, &args))
+                    .await
+                    .context("工具执行线程异常退出")?
+            }
         }
     }
 }
@@ -1,18 +1,90 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use once_cell::sync::Lazy;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::path::PathBuf;
 
+use crate::config::ToolsConfig;
 use crate::types::{FunctionDefinition, Tool};
 
-use super::builtins::{fs::FsTools, get_time, web};
+use super::builtins::fs::READ_ONLY_ERROR;
 
 /// 获取静态工具列表
 pub fn get_tools_static() -> &'static [Tool] {
     &TOOLS
 }
 
+/// 按配置过滤后的工具列表（发送给 LLM 的工具定义）；只读模式下去掉写操作工具
+pub fn enabled_tools(config: &ToolsConfig, read_only: bool) -> Vec<Tool> {
+    TOOLS
+        .iter()
+        .filter(|tool| config.is_tool_enabled(&tool.function.name))
+        .filter(|tool| !read_only || is_read_only(&tool.function.name))
+        .cloned()
+        .collect()
+}
+
+/// 只读工具：不修改 workspace 或长期记忆，计划模式下仍会实际执行
+const READ_ONLY_TOOLS: &[&str] = &[
+    "fs_read",
+    "fs_read_many",
+    "fs_list",
+    "fs_tree",
+    "fs_stat",
+    "fs_grep",
+    "web_search",
+    "web_fetch",
+    "get_time",
+    "get_weather",
+];
+
+/// 是否为只读工具
+pub fn is_read_only(name: &str) -> bool {
+    READ_ONLY_TOOLS.contains(&name)
+}
+
+/// 本次调用是否只读：只读工具，或只预览 diff 的 fs_patch
+pub fn is_read_only_call(name: &str, args: &HashMap<String, Value>) -> bool {
+    is_read_only(name) || (name == "fs_patch" && args.get("preview").and_then(Value::as_bool) == Some(true))
+}
+
+/// 执行前再次检查：内置但已在配置中禁用（或只读模式下不可用）的工具直接拒绝
+pub fn ensure_enabled(tools: &[Tool], name: &str, read_only: bool) -> Result<()> {
+    let is_builtin = TOOLS.iter().any(|t| t.function.name == name);
+    if is_builtin && !tools.iter().any(|t| t.function.name == name) {
+        if read_only && !is_read_only(name) {
+            return Err(anyhow::anyhow!("{}，无法执行：{}", READ_ONLY_ERROR, name));
+        }
+        if name == "shell_exec" {
+            return Err(anyhow::anyhow!(
+                "shell_exec 未启用（默认关闭，需在 [tools.shell] 中设置 enabled = true 并配置 allowed_commands）"
+            ));
+        }
+        return Err(anyhow::anyhow!("工具已在配置中禁用：{}", name));
+    }
+    Ok(())
+}
+
+/// 校验工具名唯一：按名称分发时重名工具会被静默遮蔽，启动时直接报错
+pub fn validate_tool_names(tools: &[Tool]) -> Result<()> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for tool in tools {
+        *counts.entry(tool.function.name.as_str()).or_default() += 1;
+    }
+
+    let mut duplicates: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(name, count)| format!("{}（{} 次）", name, count))
+        .collect();
+
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    duplicates.sort();
+    Err(anyhow::anyhow!("工具名称重复：{}", duplicates.join("，")))
+}
+
 /// 预定义的工具列表（懒加载，只初始化一次）
 static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
     vec![
@@ -33,6 +105,24 @@ static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
                 }),
             },
         },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_read_many".to_string(),
+                description: "一次读取 workspace 内的多个文件，结果按 `=== 路径 ===` 分隔，单个文件出错不影响其他文件".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "paths": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "相对于 workspace 的文件路径列表"
+                        }
+                    },
+                    "required": ["paths"]
+                }),
+            },
+        },
         Tool {
             r#type: "function".to_string(),
             function: FunctionDefinition {
@@ -54,6 +144,27 @@ static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
                 }),
             },
         },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_append".to_string(),
+                description: "追加内容到 workspace 内的文件末尾（文件不存在时创建）".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "相对于 workspace 的文件路径"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "要追加的内容"
+                        }
+                    },
+                    "required": ["path", "content"]
+                }),
+            },
+        },
         Tool {
             r#type: "function".to_string(),
             function: FunctionDefinition {
@@ -73,12 +184,63 @@ static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
                         "new_string": {
                             "type": "string",
                             "description": "替换为的新内容"
+                        },
+                        "replace_all": {
+                            "type": "boolean",
+                            "description": "替换所有出现的位置（默认 false：内容出现多次时报错）"
+                        },
+                        "occurrence": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "只替换第几处出现的内容（从 1 开始，不能与 replace_all 同时使用）"
+                        },
+                        "preview": {
+                            "type": "boolean",
+                            "description": "只预览不写入：返回改动的 unified diff（默认 false：直接修改文件）"
                         }
                     },
                     "required": ["path", "old_string", "new_string"]
                 }),
             },
         },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_delete".to_string(),
+                description: "删除 workspace 内的文件或空目录".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "相对于 workspace 的文件或目录路径"
+                        },
+                        "recursive": {
+                            "type": "boolean",
+                            "description": "是否递归删除非空目录（默认 false）"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_mkdir".to_string(),
+                description: "在 workspace 内创建目录（自动创建缺失的父目录）".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "相对于 workspace 的目录路径"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        },
         Tool {
             r#type: "function".to_string(),
             function: FunctionDefinition {
@@ -90,12 +252,101 @@ static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
                         "path": {
                             "type": "string",
                             "description": "相对于 workspace 的目录路径"
+                        },
+                        "detailed": {
+                            "type": "boolean",
+                            "description": "是否显示文件大小、行数和子目录项数（默认 false）"
                         }
                     },
                     "required": ["path"]
                 }),
             },
         },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_tree".to_string(),
+                description: "递归列出 workspace 内的目录树（目录在前，跳过隐藏项，node_modules、target 等目录不展开），一次了解项目结构".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "相对于 workspace 的目录路径（默认为 workspace 根目录）"
+                        },
+                        "max_depth": {
+                            "type": "integer",
+                            "description": "展开的最大层数（默认 3，最大 10）"
+                        }
+                    },
+                    "required": []
+                }),
+            },
+        },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_stat".to_string(),
+                description: "查询 workspace 内文件或目录的信息（是否存在、是否为目录、大小、修改时间），可在读取前确认文件大小".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "相对于 workspace 的文件或目录路径"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_archive".to_string(),
+                description: "将 workspace 内的文件打包为 zip 压缩包".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "paths": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "要打包的文件或目录路径，支持 glob（如 out/**/*.md）"
+                        },
+                        "output": {
+                            "type": "string",
+                            "description": "输出的 zip 文件路径（相对于 workspace）"
+                        }
+                    },
+                    "required": ["paths", "output"]
+                }),
+            },
+        },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "fs_grep".to_string(),
+                description: "在 workspace 文件中按正则搜索，返回匹配的文件路径、行号和内容".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "pattern": {
+                            "type": "string",
+                            "description": "正则表达式"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "搜索的文件或目录（相对于 workspace，默认整个 workspace）"
+                        },
+                        "case_insensitive": {
+                            "type": "boolean",
+                            "description": "是否忽略大小写（默认 false）"
+                        }
+                    },
+                    "required": ["pattern"]
+                }),
+            },
+        },
         Tool {
             r#type: "function".to_string(),
             function: FunctionDefinition {
@@ -124,12 +375,55 @@ static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
                         "url": {
                             "type": "string",
                             "description": "网页 URL"
+                        },
+                        "mode": {
+                            "type": "string",
+                            "enum": ["basic", "readability"],
+                            "description": "文本提取方式：basic 保留页面全部文字；readability 只提取正文并保留链接、列表和表格（默认取配置）"
+                        },
+                        "no_cache": {
+                            "type": "boolean",
+                            "description": "为 true 时忽略缓存重新抓取（默认 false，短时间内重复抓取同一 URL 会返回缓存内容）"
                         }
                     },
                     "required": ["url"]
                 }),
             },
         },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "remember".to_string(),
+                description: "将一条需要长期记住的事实写入跨会话记忆（MEMORY.md）".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "fact": {
+                            "type": "string",
+                            "description": "要记住的事实，简洁的一句话"
+                        }
+                    },
+                    "required": ["fact"]
+                }),
+            },
+        },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "forget".to_string(),
+                description: "从长期记忆中删除包含指定文本的条目".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "text": {
+                            "type": "string",
+                            "description": "要删除的记忆中包含的文本"
+                        }
+                    },
+                    "required": ["text"]
+                }),
+            },
+        },
         Tool {
             r#type: "function".to_string(),
             function: FunctionDefinition {
@@ -142,83 +436,81 @@ static TOOLS: Lazy<Vec<Tool>> = Lazy::new(|| {
                 }),
             },
         },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_weather".to_string(),
+                description: "查询指定地点的当前天气".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "location": {
+                            "type": "string",
+                            "description": "城市或地点名称，如 北京、Tokyo"
+                        }
+                    },
+                    "required": ["location"]
+                }),
+            },
+        },
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "shell_exec".to_string(),
+                description: "在 workspace 目录中运行命令（不经过 shell，只能运行配置允许的命令），返回退出码和输出".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "command": {
+                            "type": "string",
+                            "description": "命令名，如 ls、git（必须在允许列表中）"
+                        },
+                        "args": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "命令参数，原样传递（不支持管道、重定向等 shell 语法）"
+                        }
+                    },
+                    "required": ["command"]
+                }),
+            },
+        },
     ]
 });
 
-pub struct ToolRegistry {
-    fs_tools: FsTools,
-}
+/// 旧名称，保留以兼容外部调用；工具分发只在 `ToolExecutor` 中实现
+pub type ToolRegistry = super::executor::ToolExecutor;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl ToolRegistry {
-    pub fn new(workspace_root: PathBuf) -> Self {
-        ToolRegistry {
-            fs_tools: FsTools::new(workspace_root),
+    fn tool(name: &str) -> Tool {
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: name.to_string(),
+                description: String::new(),
+                parameters: serde_json::json!({ "type": "object", "properties": {} }),
+            },
         }
     }
 
-    /// 获取所有工具定义
-    pub fn get_tools(&self) -> &[Tool] {
-        &TOOLS
+    #[test]
+    fn validate_tool_names_reports_collision_with_count() {
+        let error = validate_tool_names(&[tool("fs_read"), tool("fs_read")]).unwrap_err();
+        assert_eq!(error.to_string(), "工具名称重复：fs_read（2 次）");
     }
 
-    pub async fn execute(&self, name: &str, args: &HashMap<String, Value>) -> Result<String> {
-        match name {
-            "fs_read" => {
-                let path = args
-                    .get("path")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 path 参数")?;
-                self.fs_tools.read(path)
-            }
-            "fs_write" => {
-                let path = args
-                    .get("path")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 path 参数")?;
-                let content = args
-                    .get("content")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 content 参数")?;
-                self.fs_tools.write(path, content)
-            }
-            "fs_patch" => {
-                let path = args
-                    .get("path")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 path 参数")?;
-                let old_string = args
-                    .get("old_string")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 old_string 参数")?;
-                let new_string = args
-                    .get("new_string")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 new_string 参数")?;
-                self.fs_tools.patch(path, old_string, new_string)
-            }
-            "fs_list" => {
-                let path = args
-                    .get("path")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 path 参数")?;
-                self.fs_tools.list(path)
-            }
-            "web_search" => {
-                let query = args
-                    .get("query")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 query 参数")?;
-                web::search(query).await
-            }
-            "web_fetch" => {
-                let url = args
-                    .get("url")
-                    .and_then(|v| v.as_str())
-                    .context("缺少 url 参数")?;
-                web::fetch(url).await
-            }
-            "get_time" => Ok(get_time::execute()),
-            _ => Err(anyhow::anyhow!("未知工具：{}", name)),
-        }
+    #[test]
+    fn validate_tool_names_lists_every_collision() {
+        let tools = [tool("b"), tool("a"), tool("b"), tool("a"), tool("b"), tool("c")];
+        let error = validate_tool_names(&tools).unwrap_err();
+        assert_eq!(error.to_string(), "工具名称重复：a（2 次），b（3 次）");
+    }
+
+    #[test]
+    fn builtin_tool_names_are_unique() {
+        validate_tool_names(get_tools_static()).unwrap();
     }
 }
@@ -1,5 +1,13 @@
 mod function;
 mod ollama;
+mod openai;
 
 pub use function::{FunctionCall, FunctionDefinition, Tool, ToolCall};
-pub use ollama::{Message, OllamaRequest, OllamaResponse};
+pub use ollama::{
+    image_mime_type, Message, OllamaGenerateRequest, OllamaGenerateResponse, OllamaOptions, OllamaRequest,
+    OllamaResponse, OllamaStreamChunk, TokenUsage,
+};
+pub use openai::{
+    OpenAiFunctionCall, OpenAiMessage, OpenAiRequest, OpenAiResponse, OpenAiStreamChunk,
+    OpenAiToolCall, OpenAiUsage, ResponseFormat,
+};
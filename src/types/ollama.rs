@@ -10,6 +10,40 @@ pub struct Message {
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    /// 推理模型的思考过程（与正文分开保存，不再发送给模型；Ollama 原生返回时字段名为 thinking）
+    #[serde(default, alias = "thinking", skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    /// 随消息发送的图片（base64，供支持视觉的模型使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
+    /// 消息加入会话的时间（RFC3339，旧会话中没有；不发送给模型）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    /// 生成该回复消耗的 token 数（后端返回时才有，不随消息发送或保存）
+    #[serde(skip)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// 一次请求的 token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// 按文件头识别图片的 MIME 类型，不是支持的图片格式时返回 None
+pub fn image_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -18,6 +52,46 @@ pub struct OllamaRequest {
     pub messages: Vec<Message>,
     pub tools: Option<Vec<super::function::Tool>>,
     pub stream: bool,
+    /// 输出格式约束（JSON 模式下为 "json"，结构化输出时为 JSON Schema）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<serde_json::Value>,
+    /// 采样参数（均未设置时不发送）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<OllamaOptions>,
+    /// 模型保留时长（时长字符串或秒数，负数表示一直保留）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<serde_json::Value>,
+}
+
+/// Ollama 模型参数，只序列化已设置的字段
+#[derive(Debug, Default, Serialize)]
+pub struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+}
+
+/// `/api/generate` 请求：单条提示，不带消息历史和工具
+#[derive(Debug, Serialize)]
+pub struct OllamaGenerateRequest {
+    pub model: String,
+    pub prompt: String,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<OllamaOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OllamaGenerateResponse {
+    #[serde(default)]
+    pub response: String,
+    #[serde(default)]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,4 +100,49 @@ pub struct OllamaResponse {
     pub done: bool,
     #[serde(default)]
     pub error: Option<String>,
+    /// 提示部分的 token 数
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    /// 生成部分的 token 数
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+}
+
+impl OllamaResponse {
+    /// 后端返回的 token 用量（两项都没有时为 None）
+    pub fn usage(&self) -> Option<TokenUsage> {
+        token_usage(self.prompt_eval_count, self.eval_count)
+    }
+}
+
+/// 流式响应中的单个 NDJSON 块
+#[derive(Debug, Deserialize)]
+pub struct OllamaStreamChunk {
+    #[serde(default)]
+    pub message: Option<Message>,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// 仅在最后一个块（done 为 true）中返回
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+}
+
+impl OllamaStreamChunk {
+    pub fn usage(&self) -> Option<TokenUsage> {
+        token_usage(self.prompt_eval_count, self.eval_count)
+    }
+}
+
+fn token_usage(prompt: Option<u64>, completion: Option<u64>) -> Option<TokenUsage> {
+    if prompt.is_none() && completion.is_none() {
+        return None;
+    }
+    Some(TokenUsage {
+        prompt_tokens: prompt.unwrap_or(0),
+        completion_tokens: completion.unwrap_or(0),
+    })
 }
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+
+use super::function::{FunctionCall, Tool, ToolCall};
+use super::ollama::{image_mime_type, Message, TokenUsage};
+
+/// OpenAI 兼容接口请求（/v1/chat/completions）
+#[derive(Debug, Serialize)]
+pub struct OpenAiRequest {
+    pub model: String,
+    pub messages: Vec<OpenAiMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+}
+
+/// 输出格式约束（JSON 模式下为 {"type": "json_object"}）
+#[derive(Debug, Serialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    /// type 为 json_schema 时的 Schema 定义
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<serde_json::Value>,
+}
+
+/// OpenAI 消息格式：content 可为空（带图片时为内容片段数组），工具参数为 JSON 字符串
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OpenAiToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiToolCall {
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "type", default = "default_tool_type")]
+    pub r#type: String,
+    pub function: OpenAiFunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiFunctionCall {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+fn default_tool_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiResponse {
+    #[serde(default)]
+    pub choices: Vec<OpenAiChoice>,
+    #[serde(default)]
+    pub usage: Option<OpenAiUsage>,
+}
+
+/// 非流式响应中的 token 用量
+#[derive(Debug, Deserialize)]
+pub struct OpenAiUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+impl From<OpenAiUsage> for TokenUsage {
+    fn from(usage: OpenAiUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiChoice {
+    pub message: OpenAiMessage,
+}
+
+/// 流式响应中的单个 SSE 数据块
+#[derive(Debug, Deserialize)]
+pub struct OpenAiStreamChunk {
+    #[serde(default)]
+    pub choices: Vec<OpenAiStreamChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiStreamChoice {
+    #[serde(default)]
+    pub delta: OpenAiDelta,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct OpenAiDelta {
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<OpenAiToolCallDelta>>,
+}
+
+/// 流式工具调用片段，按 index 拼接
+#[derive(Debug, Deserialize)]
+pub struct OpenAiToolCallDelta {
+    #[serde(default)]
+    pub index: usize,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub function: Option<OpenAiFunctionDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiFunctionDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+impl From<&ToolCall> for OpenAiToolCall {
+    fn from(tc: &ToolCall) -> Self {
+        // OpenAI 要求 arguments 为 JSON 字符串
+        let arguments = match &tc.function.arguments {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        OpenAiToolCall {
+            id: tc.id.clone(),
+            r#type: default_tool_type(),
+            function: OpenAiFunctionCall {
+                name: tc.function.name.clone(),
+                arguments,
+            },
+        }
+    }
+}
+
+impl From<OpenAiToolCall> for ToolCall {
+    fn from(tc: OpenAiToolCall) -> Self {
+        ToolCall {
+            id: tc.id,
+            r#type: Some(tc.r#type),
+            function: FunctionCall {
+                name: tc.function.name,
+                index: None,
+                arguments: serde_json::Value::String(tc.function.arguments),
+            },
+        }
+    }
+}
+
+impl From<&Message> for OpenAiMessage {
+    fn from(msg: &Message) -> Self {
+        let content = match &msg.images {
+            Some(images) => content_parts(&msg.content, images),
+            None => Value::String(msg.content.clone()),
+        };
+        OpenAiMessage {
+            role: msg.role.clone(),
+            content: Some(content),
+            tool_calls: msg
+                .tool_calls
+                .as_ref()
+                .map(|tcs| tcs.iter().map(OpenAiToolCall::from).collect()),
+            tool_call_id: msg.tool_call_id.clone(),
+        }
+    }
+}
+
+impl From<OpenAiMessage> for Message {
+    fn from(msg: OpenAiMessage) -> Self {
+        Message {
+            role: msg.role,
+            content: msg.content.map(content_text).unwrap_or_default(),
+            tool_calls: msg
+                .tool_calls
+                .map(|tcs| tcs.into_iter().map(ToolCall::from).collect()),
+            tool_call_id: msg.tool_call_id,
+            reasoning: None,
+            images: None,
+            usage: None,
+            timestamp: None,
+        }
+    }
+}
+
+/// 带图片的消息转为内容片段数组，图片以 data URL 发送
+fn content_parts(text: &str, images: &[String]) -> Value {
+    let mut parts = vec![serde_json::json!({ "type": "text", "text": text })];
+    for image in images {
+        // 只需解码开头几个字节即可识别格式
+        let head = STANDARD.decode(&image[..image.len().min(16)]).unwrap_or_default();
+        let mime = image_mime_type(&head).unwrap_or("image/png");
+        parts.push(serde_json::json!({
+            "type": "image_url",
+            "image_url": { "url": format!("data:{};base64,{}", mime, image) }
+        }));
+    }
+    Value::Array(parts)
+}
+
+/// 取出回复的文本内容（content 为片段数组时拼接其中的文本片段）
+fn content_text(content: Value) -> String {
+    match content {
+        Value::String(text) => text,
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 是否在终端输出中显示 emoji（`--no-emoji` 或配置 `emoji = false` 时关闭）
+static EMOJI: AtomicBool = AtomicBool::new(true);
+
+/// 关闭 emoji 时替换为文字标记的符号，其余 emoji 连同后面的一个空格直接去掉
+const SUBSTITUTES: &[(&str, &str)] = &[
+    ("✅", "[OK]"),
+    ("❌", "[ERROR]"),
+    ("⚠", "[WARN]"),
+    ("⛔", "[DISABLED]"),
+    ("🚫", "[REJECTED]"),
+    ("💡", "[TIP]"),
+    ("❓", "[?]"),
+    ("👉", ">"),
+    ("👈", "<-"),
+    ("➕", "+"),
+];
+
+/// 设置是否显示 emoji
+pub fn set_emoji(enabled: bool) {
+    EMOJI.store(enabled, Ordering::Relaxed);
+}
+
+pub fn emoji_enabled() -> bool {
+    EMOJI.load(Ordering::Relaxed)
+}
+
+/// 按当前主题处理要输出的文本：开启 emoji 时原样返回，否则替换或去掉其中的 emoji
+pub fn text(s: &str) -> Cow<'_, str> {
+    if emoji_enabled() || !s.chars().any(is_emoji) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) {
+            out.push(c);
+            continue;
+        }
+
+        // 变体选择符和零宽连接符与前面的 emoji 作为一个整体处理
+        let mut emoji = c.to_string();
+        while let Some(&next) = chars.peek() {
+            let joined = emoji.ends_with(ZWJ) && is_emoji(next);
+            if next != VARIATION_SELECTOR && next != ZWJ && !joined {
+                break;
+            }
+            emoji.push(next);
+            chars.next();
+        }
+
+        let base = emoji.trim_end_matches([VARIATION_SELECTOR, ZWJ]);
+        match SUBSTITUTES.iter().find(|(symbol, _)| *symbol == base) {
+            Some((_, substitute)) => out.push_str(substitute),
+            None => {
+                if chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// 与 `println!` 相同，输出前经过 `ui::text` 处理
+#[macro_export]
+macro_rules! ui_println {
+    () => {
+        println!()
+    };
+    ($($arg:tt)*) => {
+        println!("{}", $crate::ui::text(&format!($($arg)*)))
+    };
+}
+
+/// 与 `print!` 相同，输出前经过 `ui::text` 处理
+#[macro_export]
+macro_rules! ui_print {
+    ($($arg:tt)*) => {
+        print!("{}", $crate::ui::text(&format!($($arg)*)))
+    };
+}
+
+/// 与 `eprintln!` 相同，输出前经过 `ui::text` 处理
+#[macro_export]
+macro_rules! ui_eprintln {
+    () => {
+        eprintln!()
+    };
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::ui::text(&format!($($arg)*)))
+    };
+}
+
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+
+const ZWJ: char = '\u{200D}';
+
+/// 常见 emoji 所在的区段（含变体选择符），不包括中文标点、箭头和制表符
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1F000}'..='\u{1FAFF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{2300}'..='\u{23FF}'
+            | '\u{21A9}'..='\u{21AA}'
+            | VARIATION_SELECTOR
+    )
+}
+
+/// 日志输出（tracing）的 writer：按主题处理 emoji 后写入 stderr
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // tracing 每条日志整体写入一次；不是完整 UTF-8 时原样输出
+        match std::str::from_utf8(buf) {
+            Ok(s) if !emoji_enabled() => io::stderr().write_all(text(s).as_bytes())?,
+            _ => io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}